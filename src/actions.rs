@@ -0,0 +1,351 @@
+use bevy::prelude::*;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+/// the bindings are loaded from this file, actions that are not in it keep their default bindings
+const BINDINGS_FILE: &str = "assets/bindings.ron";
+
+/// what the player wants to do, the systems query these instead of the raw keys and buttons
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Jump,
+    Crouch,
+    /// move the camera faster
+    Boost,
+    /// move the camera slower
+    Slow,
+    /// rotate the camera with the mouse while held
+    Look,
+    PanForward,
+    PanBackward,
+    PanLeft,
+    PanRight,
+    MobaView,
+    FpsView,
+    /// pan the MOBA camera by dragging the ground
+    DragPan,
+    /// turn the MOBA camera in steps around the ground it looks at
+    RotateViewLeft,
+    RotateViewRight,
+    /// switch in between the MOBA and the FPS view
+    ToggleCameraView,
+    /// follow the player with the third person camera
+    ThirdPersonView,
+    /// look straight down at the terrain with an orthographic camera
+    TopDownView,
+    /// let the camera fly through the terrain
+    Noclip,
+    /// press the HUD button, or select and drag the IK cube under the cursor
+    Select,
+    IkMoveXPositive,
+    IkMoveXNegative,
+    IkMoveYPositive,
+    IkMoveYNegative,
+    IkMoveZPositive,
+    IkMoveZNegative,
+    IkReset,
+    Fire,
+    /// select the weapon slot at this index, starting from 0
+    WeaponSlot(usize),
+    /// select the IK cube holding the mining tool
+    SelectTool,
+    /// press the HUD hotbar button at this index, starting from 0 at the top left
+    Hotbar(usize),
+    /// switch the gamepad sticks in between driving the camera and the IK hand
+    ToggleStickMode,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum InputButton {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// a button of any of the connected gamepads
+    Gamepad(GamepadButtonType),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Stick {
+    Left,
+    Right,
+}
+
+/// what the gamepad sticks are driving
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StickMode {
+    /// the left stick moves the camera and the right stick looks around
+    Camera,
+    /// the left stick moves the IK hand in X and Z and the right stick in Y
+    IkArm,
+}
+
+/// buttons that have to be held together to trigger an action
+pub type Chord = Vec<InputButton>;
+
+/// the state of the actions for this frame, updated from the bindings before the other systems run
+///
+/// when the buttons of a chord are held, the actions bound to only a part of that chord
+/// are not triggered, so SHIFT + X does not also trigger the actions bound to SHIFT or to X
+#[derive(Default)]
+pub struct ActionMap {
+    bindings: HashMap<Action, Vec<Chord>>,
+    pressed: HashSet<Action>,
+    previous: HashSet<Action>,
+    left_stick: Vec2,
+    right_stick: Vec2,
+    /// the actions pressed by something other than the bindings, for the next frame
+    triggered: HashSet<Action>,
+}
+
+impl ActionMap {
+    pub fn new(bindings: HashMap<Action, Vec<Chord>>) -> Self {
+        ActionMap {
+            bindings,
+            ..Default::default()
+        }
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action) && !self.previous.contains(&action)
+    }
+
+    /// press the action for a single frame starting from the next one,
+    /// this is how the HUD buttons do the same as the keys bound to the actions
+    pub fn trigger(&mut self, action: Action) {
+        self.triggered.insert(action);
+    }
+
+    /// the position of the gamepad stick, each axis is in -1..1
+    pub fn stick(&self, stick: Stick) -> Vec2 {
+        match stick {
+            Stick::Left => self.left_stick,
+            Stick::Right => self.right_stick,
+        }
+    }
+
+    /// the actions that are bound to the exact same chord, these can never be told apart
+    pub fn conflicts(&self) -> Vec<(Action, Action, Chord)> {
+        let chords: Vec<(Action, HashSet<InputButton>)> = self
+            .bindings
+            .iter()
+            .flat_map(|(action, chords)| {
+                chords
+                    .iter()
+                    .map(move |chord| (*action, chord.iter().copied().collect()))
+            })
+            .collect();
+        let mut conflicts = vec![];
+        for (i, (action, chord)) in chords.iter().enumerate() {
+            for (other_action, other_chord) in chords.iter().skip(i + 1) {
+                if action != other_action && chord == other_chord {
+                    conflicts.push((*action, *other_action, chord.iter().copied().collect()));
+                }
+            }
+        }
+        conflicts
+    }
+
+    fn update(&mut self, is_held: impl Fn(&InputButton) -> bool) {
+        let held: Vec<(Action, &Chord)> = self
+            .bindings
+            .iter()
+            .flat_map(|(action, chords)| chords.iter().map(move |chord| (*action, chord)))
+            .filter(|(_, chord)| !chord.is_empty() && chord.iter().all(|button| is_held(button)))
+            .collect();
+
+        let mut pressed: HashSet<Action> = held
+            .iter()
+            .filter(|(_, chord)| {
+                // a longer chord which contains all of this one takes precedence
+                !held.iter().any(|(_, other)| {
+                    other.len() > chord.len() && chord.iter().all(|button| other.contains(button))
+                })
+            })
+            .map(|(action, _)| *action)
+            .collect();
+        pressed.extend(self.triggered.drain());
+        self.previous = std::mem::replace(&mut self.pressed, pressed);
+    }
+}
+
+fn default_bindings() -> HashMap<Action, Vec<Chord>> {
+    use InputButton::{Gamepad, Key, Mouse};
+    let mut bindings = HashMap::new();
+    let mut bind = |action, chord: &[InputButton]| {
+        bindings
+            .entry(action)
+            .or_insert_with(Vec::new)
+            .push(chord.to_vec());
+    };
+    bind(Action::MoveForward, &[Key(KeyCode::W)]);
+    bind(Action::MoveBackward, &[Key(KeyCode::S)]);
+    bind(Action::MoveLeft, &[Key(KeyCode::A)]);
+    bind(Action::MoveRight, &[Key(KeyCode::D)]);
+    bind(Action::MoveUp, &[Key(KeyCode::E)]);
+    bind(Action::MoveUp, &[Gamepad(GamepadButtonType::RightTrigger)]);
+    bind(Action::MoveDown, &[Key(KeyCode::Q)]);
+    bind(Action::MoveDown, &[Gamepad(GamepadButtonType::LeftTrigger)]);
+    bind(Action::Jump, &[Key(KeyCode::Space)]);
+    bind(Action::Jump, &[Gamepad(GamepadButtonType::South)]);
+    bind(Action::Crouch, &[Key(KeyCode::C)]);
+    bind(Action::Crouch, &[Gamepad(GamepadButtonType::East)]);
+    bind(Action::Boost, &[Key(KeyCode::LShift)]);
+    bind(Action::Boost, &[Gamepad(GamepadButtonType::LeftThumb)]);
+    bind(Action::Slow, &[Key(KeyCode::LControl)]);
+    bind(Action::Look, &[Mouse(MouseButton::Right)]);
+    bind(Action::PanForward, &[Key(KeyCode::Up)]);
+    bind(Action::PanBackward, &[Key(KeyCode::Down)]);
+    bind(Action::PanLeft, &[Key(KeyCode::Left)]);
+    bind(Action::PanRight, &[Key(KeyCode::Right)]);
+    bind(Action::MobaView, &[Key(KeyCode::M)]);
+    bind(Action::DragPan, &[Mouse(MouseButton::Middle)]);
+    bind(Action::RotateViewLeft, &[Key(KeyCode::Comma)]);
+    bind(
+        Action::RotateViewLeft,
+        &[Gamepad(GamepadButtonType::DPadLeft)],
+    );
+    bind(Action::RotateViewRight, &[Key(KeyCode::Period)]);
+    bind(
+        Action::RotateViewRight,
+        &[Gamepad(GamepadButtonType::DPadRight)],
+    );
+    bind(Action::FpsView, &[Key(KeyCode::F)]);
+    bind(Action::ToggleCameraView, &[Key(KeyCode::V)]);
+    bind(Action::ThirdPersonView, &[Key(KeyCode::T)]);
+    bind(Action::TopDownView, &[Key(KeyCode::O)]);
+    bind(Action::Noclip, &[Key(KeyCode::N)]);
+    bind(
+        Action::ToggleCameraView,
+        &[Gamepad(GamepadButtonType::Select)],
+    );
+    bind(Action::Select, &[Mouse(MouseButton::Left)]);
+    bind(Action::IkMoveXPositive, &[Key(KeyCode::X)]);
+    bind(
+        Action::IkMoveXNegative,
+        &[Key(KeyCode::LShift), Key(KeyCode::X)],
+    );
+    bind(Action::IkMoveYPositive, &[Key(KeyCode::Y)]);
+    bind(
+        Action::IkMoveYNegative,
+        &[Key(KeyCode::LShift), Key(KeyCode::Y)],
+    );
+    bind(Action::IkMoveZPositive, &[Key(KeyCode::Z)]);
+    bind(
+        Action::IkMoveZNegative,
+        &[Key(KeyCode::LShift), Key(KeyCode::Z)],
+    );
+    bind(Action::IkReset, &[Key(KeyCode::R)]);
+    bind(Action::IkReset, &[Gamepad(GamepadButtonType::West)]);
+    bind(
+        Action::Fire,
+        &[Key(KeyCode::LShift), Mouse(MouseButton::Left)],
+    );
+    bind(Action::Fire, &[Gamepad(GamepadButtonType::RightTrigger2)]);
+    bind(
+        Action::ToggleStickMode,
+        &[Gamepad(GamepadButtonType::North)],
+    );
+    // the number keys press the first buttons of the hotbar, the weapons are on those
+    let hotbar_keys = [
+        KeyCode::Key1,
+        KeyCode::Key2,
+        KeyCode::Key3,
+        KeyCode::Key4,
+        KeyCode::Key5,
+        KeyCode::Key6,
+        KeyCode::Key7,
+        KeyCode::Key8,
+        KeyCode::Key9,
+        KeyCode::Key0,
+    ];
+    for (slot, key) in hotbar_keys.into_iter().enumerate() {
+        bind(Action::Hotbar(slot), &[Key(key)]);
+    }
+    bindings
+}
+
+fn load_bindings() -> HashMap<Action, Vec<Chord>> {
+    let mut bindings = default_bindings();
+    let loaded = std::fs::read_to_string(BINDINGS_FILE)
+        .map_err(|e| e.to_string())
+        .and_then(|content| {
+            ron::from_str::<HashMap<Action, Vec<Chord>>>(&content).map_err(|e| e.to_string())
+        });
+    match loaded {
+        Ok(loaded) => bindings.extend(loaded),
+        Err(e) => warn!(
+            "unable to load {}: {}, using the default bindings",
+            BINDINGS_FILE, e
+        ),
+    }
+    bindings
+}
+
+pub(crate) fn setup(mut commands: Commands) {
+    let action_map = ActionMap::new(load_bindings());
+    for (action, other_action, chord) in action_map.conflicts() {
+        warn!(
+            "{:?} and {:?} are both bound to {:?}",
+            action, other_action, chord
+        );
+    }
+    commands.insert_resource(action_map);
+    commands.insert_resource(StickMode::Camera);
+}
+
+pub(crate) fn update_actions(
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    mut action_map: ResMut<ActionMap>,
+) {
+    action_map.update(|button| match button {
+        InputButton::Key(key) => keys.pressed(*key),
+        InputButton::Mouse(mouse_button) => mouse_buttons.pressed(*mouse_button),
+        InputButton::Gamepad(button_type) => gamepads
+            .iter()
+            .any(|gamepad| gamepad_buttons.pressed(GamepadButton(*gamepad, *button_type))),
+    });
+
+    // the sticks of the first connected gamepad, the dead zone is already applied by bevy
+    let axis = |gamepad: Gamepad, axis_type| {
+        gamepad_axes
+            .get(GamepadAxis(gamepad, axis_type))
+            .unwrap_or(0.0)
+    };
+    let (left_stick, right_stick) = match gamepads.iter().next() {
+        Some(gamepad) => (
+            Vec2::new(
+                axis(*gamepad, GamepadAxisType::LeftStickX),
+                axis(*gamepad, GamepadAxisType::LeftStickY),
+            ),
+            Vec2::new(
+                axis(*gamepad, GamepadAxisType::RightStickX),
+                axis(*gamepad, GamepadAxisType::RightStickY),
+            ),
+        ),
+        None => (Vec2::ZERO, Vec2::ZERO),
+    };
+    action_map.left_stick = left_stick;
+    action_map.right_stick = right_stick;
+}
+
+pub(crate) fn toggle_stick_mode(actions: Res<ActionMap>, mut stick_mode: ResMut<StickMode>) {
+    if actions.just_pressed(Action::ToggleStickMode) {
+        *stick_mode = match *stick_mode {
+            StickMode::Camera => StickMode::IkArm,
+            StickMode::IkArm => StickMode::Camera,
+        };
+    }
+}
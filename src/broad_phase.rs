@@ -0,0 +1,289 @@
+use bevy::prelude::*;
+use parry3d::{
+    bounding_volume::AABB,
+    math::{Point, Real},
+    partitioning::QBVH,
+    query::{visitors::RayIntersectionsVisitor, Ray, RayIntersection},
+    shape::{Cuboid, Shape},
+};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+/// the boxes in the tree are grown by this factor when they are refitted,
+/// so entities moving a little don't have to refit the tree every frame
+const BROAD_PHASE_DILATION: Real = 0.1;
+
+/// the bounding box of the component in its own space
+pub trait Bounded {
+    fn local_aabb(&self) -> AABB;
+}
+
+impl Bounded for Cuboid {
+    fn local_aabb(&self) -> AABB {
+        self.compute_local_aabb()
+    }
+}
+
+/// the bounding box in world space of the component placed by the whole transform
+pub(crate) fn world_aabb<T: Bounded>(transform: &GlobalTransform, component: &T) -> AABB {
+    let local_to_world = transform.compute_matrix();
+    let corners: Vec<Point<Real>> = component
+        .local_aabb()
+        .vertices()
+        .iter()
+        .map(|corner| {
+            let corner: Vec3 = (*corner).into();
+            local_to_world.transform_point3(corner).into()
+        })
+        .collect();
+    AABB::from_points(&corners)
+}
+
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub struct BroadPhaseUpdate;
+
+/// the entities with the component kept in a tree of their world bounding boxes,
+/// a ray is only cast against the entities it goes through the bounding box of
+pub struct BroadPhase<T> {
+    qbvh: QBVH<u32>,
+    /// the entity at each leaf of the tree
+    entities: Vec<Entity>,
+    /// the world bounding box at each leaf of the tree
+    aabbs: Vec<AABB>,
+    leaves: HashMap<Entity, u32>,
+    _component: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for BroadPhase<T> {
+    fn default() -> Self {
+        BroadPhase {
+            qbvh: QBVH::new(),
+            entities: vec![],
+            aabbs: vec![],
+            leaves: HashMap::new(),
+            _component: PhantomData,
+        }
+    }
+}
+
+impl<T> BroadPhase<T> {
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    /// build the tree again from all of the entities with their world bounding boxes
+    pub fn rebuild(&mut self, entities: impl Iterator<Item = (Entity, AABB)>) {
+        self.entities.clear();
+        self.aabbs.clear();
+        self.leaves.clear();
+        for (leaf, (entity, aabb)) in entities.enumerate() {
+            self.entities.push(entity);
+            self.aabbs.push(aabb);
+            self.leaves.insert(entity, leaf as u32);
+        }
+        let leaves = self
+            .aabbs
+            .iter()
+            .enumerate()
+            .map(|(leaf, aabb)| (leaf as u32, *aabb));
+        self.qbvh.clear_and_rebuild(leaves, BROAD_PHASE_DILATION);
+    }
+
+    /// the entity has moved, the tree is refitted to it in `refit`,
+    /// returns false if the entity is not in the tree
+    pub fn moved(&mut self, entity: Entity, aabb: AABB) -> bool {
+        match self.leaves.get(&entity) {
+            Some(leaf) => {
+                self.aabbs[*leaf as usize] = aabb;
+                self.qbvh.pre_update(*leaf);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// grow the boxes of the tree around the entities that have moved
+    pub fn refit(&mut self) {
+        let aabbs = &self.aabbs;
+        self.qbvh
+            .update(|leaf| aabbs[*leaf as usize], BROAD_PHASE_DILATION);
+    }
+
+    /// the entities the ray goes through the bounding box of, within the time of impact
+    pub fn candidates(&self, ray: &Ray, max_toi: Real) -> Vec<Entity> {
+        let mut candidates = vec![];
+        let mut collect = |leaf: &u32| {
+            candidates.push(self.entities[*leaf as usize]);
+            true
+        };
+        let mut visitor = RayIntersectionsVisitor::new(ray, max_toi, &mut collect);
+        self.qbvh.traverse_depth_first(&mut visitor);
+        candidates
+    }
+
+    /// the closest of the entities hit by the ray, cast against each of the candidates
+    pub fn cast_ray<F>(
+        &self,
+        ray: &Ray,
+        max_toi: Real,
+        mut cast: F,
+    ) -> Option<(Entity, RayIntersection)>
+    where
+        F: FnMut(Entity) -> Option<RayIntersection>,
+    {
+        self.candidates(ray, max_toi)
+            .into_iter()
+            .filter_map(|entity| cast(entity).map(|intersection| (entity, intersection)))
+            .min_by(|a, b| a.1.toi.partial_cmp(&b.1.toi).unwrap_or(Ordering::Equal))
+    }
+}
+
+/// keeps the `BroadPhase` of the entities with the component up to date
+pub struct BroadPhasePlugin<T>(PhantomData<fn() -> T>);
+
+impl<T> Default for BroadPhasePlugin<T> {
+    fn default() -> Self {
+        BroadPhasePlugin(PhantomData)
+    }
+}
+
+impl<T> Plugin for BroadPhasePlugin<T>
+where
+    T: Component + Bounded,
+{
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BroadPhase<T>>()
+            .add_system(update_broad_phase::<T>.label(BroadPhaseUpdate));
+    }
+}
+
+/// the tree is rebuilt when entities are added or removed,
+/// the entities that have moved only refit the part of the tree they are in
+fn update_broad_phase<T>(
+    mut broad_phase: ResMut<BroadPhase<T>>,
+    added: Query<(), Added<T>>,
+    all: Query<(Entity, &GlobalTransform, &T)>,
+    moved: Query<(Entity, &GlobalTransform, &T), Or<(Changed<GlobalTransform>, Changed<T>)>>,
+) where
+    T: Component + Bounded,
+{
+    let removed = all.iter().count() != broad_phase.len();
+    if removed || added.iter().next().is_some() {
+        broad_phase.rebuild(
+            all.iter()
+                .map(|(entity, transform, component)| (entity, world_aabb(transform, component))),
+        );
+        return;
+    }
+    for (entity, transform, component) in moved.iter() {
+        broad_phase.moved(entity, world_aabb(transform, component));
+    }
+    broad_phase.refit();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::selector;
+    use rand::Rng;
+    use std::time::{Duration, Instant};
+
+    /// how many cubes the benchmark scatters around
+    const BENCH_ENTITIES: usize = 10_000;
+    /// how many rays the benchmark casts with each of the methods
+    const BENCH_RAYS: usize = 1_000;
+    /// the cubes of the benchmark are scattered in a cube this many meters wide
+    const BENCH_WORLD_SIZE: f32 = 200.0;
+
+    /// the closest entity hit by each of the rays, and the time it took to cast all of them
+    fn time_rays(
+        rays: &[Ray],
+        mut cast: impl FnMut(&Ray) -> Option<Entity>,
+    ) -> (Vec<Option<Entity>>, Duration) {
+        let start = Instant::now();
+        let hits = rays.iter().map(|ray| cast(ray)).collect();
+        (hits, start.elapsed())
+    }
+
+    /// compare casting rays through the broad phase against casting them at every entity,
+    /// run it with `cargo test --release bench_broad_phase -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_broad_phase() {
+        let mut rng = rand::thread_rng();
+        let mut random_location = || {
+            Vec3::new(rng.gen(), rng.gen(), rng.gen()) * BENCH_WORLD_SIZE - BENCH_WORLD_SIZE / 2.0
+        };
+        let mut cubes: Vec<(Entity, GlobalTransform, Cuboid)> = (0..BENCH_ENTITIES)
+            .map(|i| {
+                let transform = GlobalTransform::from_translation(random_location());
+                (
+                    Entity::from_raw(i as u32),
+                    transform,
+                    Cuboid::new(Vec3::splat(0.5).into()),
+                )
+            })
+            .collect();
+        let rays: Vec<Ray> = (0..BENCH_RAYS)
+            .map(|_| {
+                let origin = random_location();
+                let dir = (random_location() - origin).normalize_or_zero();
+                Ray::new(origin.into(), dir.into())
+            })
+            .collect();
+
+        let mut broad_phase = BroadPhase::<Cuboid>::default();
+        let start = Instant::now();
+        broad_phase.rebuild(
+            cubes
+                .iter()
+                .map(|(entity, transform, cube)| (*entity, world_aabb(transform, cube))),
+        );
+        let build = start.elapsed();
+
+        // a tenth of the cubes move a little, as they would in between frames
+        let start = Instant::now();
+        let mut moved = 0;
+        for (entity, transform, cube) in cubes.iter_mut().step_by(10) {
+            transform.translation += Vec3::new(0.1, 0.0, 0.1);
+            assert!(broad_phase.moved(*entity, world_aabb(transform, cube)));
+            moved += 1;
+        }
+        broad_phase.refit();
+        let refit = start.elapsed();
+
+        let (linear_hits, linear) = time_rays(&rays, |ray| {
+            cubes
+                .iter()
+                .filter_map(|(entity, transform, cube)| {
+                    selector::cast_ray(ray, transform, cube, f32::INFINITY)
+                        .map(|intersection| (*entity, intersection.toi))
+                })
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+                .map(|(entity, _)| entity)
+        });
+        let (tree_hits, tree) = time_rays(&rays, |ray| {
+            broad_phase
+                .cast_ray(ray, f32::INFINITY, |entity| {
+                    let (_, transform, cube) = &cubes[entity.id() as usize];
+                    selector::cast_ray(ray, transform, cube, f32::INFINITY)
+                })
+                .map(|(entity, _)| entity)
+        });
+
+        println!("{} entities, {} rays", BENCH_ENTITIES, BENCH_RAYS);
+        println!("building the broad phase: {:?}", build);
+        println!("refitting {} moved entities: {:?}", moved, refit);
+        println!("linear scan: {:?}", linear);
+        println!("broad phase: {:?}", tree);
+        println!(
+            "speedup: {:.1}x",
+            linear.as_secs_f64() / tree.as_secs_f64().max(f64::EPSILON)
+        );
+
+        for (ray, (linear_hit, tree_hit)) in rays.iter().zip(linear_hits.iter().zip(&tree_hits)) {
+            assert_eq!(linear_hit, tree_hit, "ray {:?}", ray);
+        }
+    }
+}
@@ -0,0 +1,700 @@
+use crate::actions::{Action, ActionMap, Stick, StickMode};
+use crate::camera_settings::CameraSettings;
+use crate::player::{Player, PLAYER_HEIGHT};
+use crate::terrain::Terrain;
+use crate::{
+    DefaultCamera, FPS_VIEWING_HEIGHT, FPS_VIEW_PITCH, ISOMETRIC_VIEWING_HEIGHT,
+    ISOMETRIC_VIEW_PITCH, TOP_DOWN_VIEW_PITCH,
+};
+use bevy::input::mouse::{MouseMotion, MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+use bevy::render::camera::{DepthCalculation, ScalingMode};
+use dolly::prelude::{Arm, CameraRig, LookAt, Position, YawPitch};
+use parry3d::query::Ray;
+use serde::Deserialize;
+
+/// the orbit camera looks at this height of the player
+const ORBIT_PIVOT_HEIGHT: f32 = PLAYER_HEIGHT * 0.9;
+const ORBIT_DEFAULT_DISTANCE: f32 = 6.0;
+const ORBIT_MIN_DISTANCE: f32 = 2.0;
+const ORBIT_MAX_DISTANCE: f32 = 20.0;
+/// how much one notch of the scroll wheel zooms the orbit camera
+const ORBIT_ZOOM_STEP: f32 = 1.0;
+/// scrolling this many pixels on a touchpad counts as one notch of the scroll wheel
+const PIXELS_PER_SCROLL_LINE: f32 = 20.0;
+const ORBIT_DEFAULT_PITCH: f32 = -20.0;
+const ORBIT_MIN_PITCH: f32 = -80.0;
+const ORBIT_MAX_PITCH: f32 = 60.0;
+/// how far the camera is kept from the terrain it is pulled in front of
+const ORBIT_CAMERA_RADIUS: f32 = 0.3;
+/// the closest the camera is pulled in to the player
+const ORBIT_MIN_PULL_IN: f32 = 0.5;
+/// how fast the camera moves back out once the terrain is no longer in the way
+const ORBIT_RELAX_RATE: f32 = 4.0;
+/// the lowest and highest the MOBA camera zooms to
+const MOBA_MIN_HEIGHT: f32 = 8.0;
+const MOBA_MAX_HEIGHT: f32 = 40.0;
+/// how much one notch of the scroll wheel zooms the MOBA camera
+const MOBA_ZOOM_STEP: f32 = 2.0;
+/// the cursor scrolls the MOBA camera when it is this many pixels from the edge of the window
+const EDGE_SCROLL_MARGIN: f32 = 10.0;
+/// meters per second the MOBA camera scrolls at the default height, faster when zoomed out
+const EDGE_SCROLL_SPEED: f32 = 25.0;
+/// degrees the MOBA camera turns with each rotate action
+const MOBA_ROTATION_STEP: f32 = 45.0;
+/// how many meters of the ground the top down view shows above and below the middle of the window
+const TOP_DOWN_DEFAULT_SIZE: f32 = 20.0;
+const TOP_DOWN_MIN_SIZE: f32 = 5.0;
+const TOP_DOWN_MAX_SIZE: f32 = 100.0;
+/// how much one notch of the scroll wheel zooms the top down view
+const TOP_DOWN_ZOOM_STEP: f32 = 2.0;
+/// the MOBA and top down cameras are kept within this distance from the origin along X and Z
+const MOBA_MAP_HALF_SIZE: f32 = 128.0;
+/// radius of the sphere swept against the terrain when the camera moves
+const CAMERA_COLLISION_RADIUS: f32 = 0.3;
+/// the lowest the camera goes above the terrain below it
+const CAMERA_MIN_GROUND_HEIGHT: f32 = 1.0;
+
+/// the camera views the player can switch in between
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CameraView {
+    /// first person shooter, looking straight ahead from the eyes of the player
+    Fps,
+    /// iso metric view like in popular MOBAs, high up looking down
+    Moba,
+    /// orbiting behind the player
+    ThirdPerson,
+    /// orthographic, looking straight down to plan the mining
+    TopDown,
+}
+
+impl CameraView {
+    /// the pose the camera transitions to from the current one,
+    /// `ground` is the height of the ground under the camera when no player carries it
+    fn pose(
+        self,
+        current: ViewPose,
+        ground: Option<f32>,
+        moba_camera: &MobaCamera,
+        settings: &CameraSettings,
+    ) -> ViewPose {
+        let fov = settings.fov(self);
+        match self {
+            // the player carries the camera at its eye height,
+            // flying freely it goes down to the same height above the ground
+            CameraView::Fps => ViewPose {
+                height: ground.map_or(current.height, |ground| ground + FPS_VIEWING_HEIGHT),
+                pitch: FPS_VIEW_PITCH,
+                fov,
+            },
+            CameraView::Moba => ViewPose {
+                height: moba_camera.height,
+                pitch: ISOMETRIC_VIEW_PITCH,
+                fov,
+            },
+            // the projection turns orthographic once the camera is looking straight down
+            CameraView::TopDown => ViewPose {
+                height: moba_camera.top_down_height(settings),
+                pitch: TOP_DOWN_VIEW_PITCH,
+                fov,
+            },
+            // the orbit camera places the camera, only the field of view is animated
+            CameraView::ThirdPerson => ViewPose { fov, ..current },
+        }
+    }
+
+    fn toggled(self) -> Self {
+        match self {
+            CameraView::Fps => CameraView::Moba,
+            CameraView::Moba | CameraView::ThirdPerson | CameraView::TopDown => CameraView::Fps,
+        }
+    }
+}
+
+/// easing functions mapping the progress of a transition in 0..1 to 0..1
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+        }
+    }
+}
+
+/// the parts of the camera that change in between the views, angles are in degrees
+#[derive(Clone, Copy, Debug)]
+struct ViewPose {
+    height: f32,
+    pitch: f32,
+    fov: f32,
+}
+
+impl ViewPose {
+    fn lerp(self, other: ViewPose, t: f32) -> ViewPose {
+        ViewPose {
+            height: self.height + (other.height - self.height) * t,
+            pitch: self.pitch + (other.pitch - self.pitch) * t,
+            fov: self.fov + (other.fov - self.fov) * t,
+        }
+    }
+}
+
+struct Transition {
+    from: ViewPose,
+    to: ViewPose,
+    elapsed: f32,
+}
+
+/// the current camera view and the transition going to it
+pub struct CameraMode {
+    pub view: CameraView,
+    transition: Option<Transition>,
+}
+
+impl CameraMode {
+    pub fn new(view: CameraView) -> Self {
+        CameraMode {
+            view,
+            transition: None,
+        }
+    }
+
+    /// whether the camera is still moving to the current view
+    pub fn is_transitioning(&self) -> bool {
+        self.transition.is_some()
+    }
+
+    /// whether the camera is carried around by the player instead of flying freely,
+    /// the FPS view flies freely in noclip
+    pub fn follows_player(&self, collision: &CameraCollision) -> bool {
+        match self.view {
+            CameraView::Fps => collision.enabled,
+            CameraView::ThirdPerson => true,
+            CameraView::Moba | CameraView::TopDown => false,
+        }
+    }
+
+    /// start moving to the view from where the camera is now,
+    /// this also picks up from the middle of a transition
+    fn switch_to(
+        &mut self,
+        view: CameraView,
+        camera_rig: &mut CameraRig,
+        fov: f32,
+        ground: Option<f32>,
+        moba_camera: &MobaCamera,
+        settings: &CameraSettings,
+    ) {
+        self.view = view;
+        let from = ViewPose {
+            height: camera_rig.driver_mut::<Position>().position.y,
+            pitch: camera_rig.driver_mut::<YawPitch>().pitch_degrees,
+            fov: fov.to_degrees(),
+        };
+        self.transition = Some(Transition {
+            from,
+            to: view.pose(from, ground, moba_camera, settings),
+            elapsed: 0.0,
+        });
+    }
+}
+
+/// look through an orthographic projection showing this many meters of the ground
+/// above and below the middle of the window
+pub(crate) fn use_orthographic(commands: &mut Commands, camera: Entity, size: f32) {
+    commands
+        .entity(camera)
+        .remove::<PerspectiveProjection>()
+        .insert(OrthographicProjection {
+            scaling_mode: ScalingMode::FixedVertical,
+            depth_calculation: DepthCalculation::Distance,
+            scale: size,
+            ..Default::default()
+        });
+}
+
+/// look through a perspective projection again, the field of view is in degrees
+fn use_perspective(commands: &mut Commands, camera: Entity, fov: f32) {
+    commands
+        .entity(camera)
+        .remove::<OrthographicProjection>()
+        .insert(PerspectiveProjection {
+            fov: fov.to_radians(),
+            ..Default::default()
+        });
+}
+
+/// switch the camera view once when the view actions are pressed
+pub(crate) fn change_camera_mode(
+    mut commands: Commands,
+    actions: Res<ActionMap>,
+    settings: Res<CameraSettings>,
+    mut camera_mode: ResMut<CameraMode>,
+    moba_camera: Res<MobaCamera>,
+    mut camera_rig: ResMut<CameraRig>,
+    cameras: Query<(Entity, Option<&PerspectiveProjection>), With<DefaultCamera>>,
+    terrain: Res<Terrain>,
+    collision: Res<CameraCollision>,
+) {
+    let view = if actions.just_pressed(Action::MobaView) {
+        CameraView::Moba
+    } else if actions.just_pressed(Action::FpsView) {
+        CameraView::Fps
+    } else if actions.just_pressed(Action::ThirdPersonView) {
+        CameraView::ThirdPerson
+    } else if actions.just_pressed(Action::TopDownView) {
+        CameraView::TopDown
+    } else if actions.just_pressed(Action::ToggleCameraView) {
+        camera_mode.view.toggled()
+    } else {
+        return;
+    };
+    if view == camera_mode.view {
+        return;
+    }
+    let (camera, perspective) = match cameras.iter().next() {
+        Some(camera) => camera,
+        None => return,
+    };
+    let fov = match perspective {
+        Some(projection) => projection.fov,
+        // leaving the top down view, the camera is high enough up for its field of view
+        // to show as much of the ground as the orthographic projection did
+        None => {
+            let fov = settings.fov(camera_mode.view);
+            use_perspective(&mut commands, camera, fov);
+            fov.to_radians()
+        }
+    };
+    // in noclip there is no player carrying the camera in the FPS view
+    let ground = if collision.enabled {
+        None
+    } else {
+        let position = camera_rig.driver_mut::<Position>().position;
+        Some(ground_height(&terrain, position))
+    };
+    camera_mode.switch_to(view, &mut camera_rig, fov, ground, &moba_camera, &settings);
+}
+
+/// the height of the top of the terrain below the position, 0 when there is nothing below
+fn ground_height(terrain: &Terrain, position: Vec3) -> f32 {
+    let ray = Ray::new(position.into(), (-Vec3::Y).into());
+    terrain
+        .cast_ray(&ray, f32::INFINITY)
+        .map_or(0.0, |hit| position.y - hit.toi)
+}
+
+/// move the height, pitch and field of view of the camera towards the current view
+pub(crate) fn animate_camera_mode(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<CameraSettings>,
+    moba_camera: Res<MobaCamera>,
+    mut camera_mode: ResMut<CameraMode>,
+    mut camera_rig: ResMut<CameraRig>,
+    cameras: Query<Entity, With<DefaultCamera>>,
+    mut projections: Query<&mut PerspectiveProjection, With<DefaultCamera>>,
+    collision: Res<CameraCollision>,
+) {
+    let duration = settings.transition_duration;
+    let easing = settings.transition_easing;
+    let (pose, done) = match camera_mode.transition.as_mut() {
+        Some(transition) => {
+            transition.elapsed += time.delta_seconds();
+            let t = if duration > 0.0 {
+                transition.elapsed / duration
+            } else {
+                1.0
+            };
+            (
+                transition.from.lerp(transition.to, easing.apply(t)),
+                t >= 1.0,
+            )
+        }
+        None => return,
+    };
+
+    if !camera_mode.follows_player(&collision) {
+        camera_rig.driver_mut::<Position>().position.y = pose.height;
+    }
+    if camera_mode.view != CameraView::ThirdPerson {
+        camera_rig.driver_mut::<YawPitch>().pitch_degrees = pose.pitch;
+    }
+    for mut projection in projections.iter_mut() {
+        projection.fov = pose.fov.to_radians();
+    }
+
+    if done {
+        if camera_mode.view == CameraView::TopDown {
+            for camera in cameras.iter() {
+                use_orthographic(&mut commands, camera, moba_camera.top_down_size);
+            }
+        }
+        camera_mode.transition = None;
+    }
+}
+
+/// the zoom of the MOBA and top down cameras
+pub struct MobaCamera {
+    /// how high the camera is, in between the min and max MOBA height
+    pub height: f32,
+    /// how many meters of the ground the top down view shows above and below the middle of the window
+    pub top_down_size: f32,
+}
+
+impl MobaCamera {
+    pub fn new() -> Self {
+        MobaCamera {
+            height: ISOMETRIC_VIEWING_HEIGHT,
+            top_down_size: TOP_DOWN_DEFAULT_SIZE,
+        }
+    }
+
+    /// how far ahead of the camera along the ground it is looking at in this view
+    pub fn look_ahead(&self, view: CameraView) -> f32 {
+        match view {
+            CameraView::Moba => self.height / (-ISOMETRIC_VIEW_PITCH).to_radians().tan(),
+            // looking straight down, or following the player
+            CameraView::TopDown | CameraView::Fps | CameraView::ThirdPerson => 0.0,
+        }
+    }
+
+    /// how high the top down camera is, a perspective camera with the MOBA field of view
+    /// sees as much of the ground from there as the orthographic projection does
+    pub fn top_down_height(&self, settings: &CameraSettings) -> f32 {
+        self.top_down_size / (settings.moba_fov.to_radians() / 2.0).tan()
+    }
+}
+
+/// scroll the MOBA and top down cameras at the edges of the window and by dragging with the
+/// middle mouse button, zoom them with the scroll wheel and turn them around the ground they
+/// look at in steps
+pub(crate) fn moba_camera(
+    time: Res<Time>,
+    actions: Res<ActionMap>,
+    settings: Res<CameraSettings>,
+    windows: Res<Windows>,
+    camera_mode: Res<CameraMode>,
+    mut mouse_motion_events: EventReader<MouseMotion>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut moba_camera: ResMut<MobaCamera>,
+    mut camera_rig: ResMut<CameraRig>,
+    projections: Query<&PerspectiveProjection, With<DefaultCamera>>,
+    mut orthographic_projections: Query<&mut OrthographicProjection, With<DefaultCamera>>,
+) {
+    let top_down = match camera_mode.view {
+        CameraView::Moba => false,
+        CameraView::TopDown => true,
+        CameraView::Fps | CameraView::ThirdPerson => return,
+    };
+
+    for event in mouse_wheel_events.iter() {
+        let notches = match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / PIXELS_PER_SCROLL_LINE,
+        };
+        if top_down {
+            moba_camera.top_down_size = (moba_camera.top_down_size - notches * TOP_DOWN_ZOOM_STEP)
+                .clamp(TOP_DOWN_MIN_SIZE, TOP_DOWN_MAX_SIZE);
+            for mut projection in orthographic_projections.iter_mut() {
+                projection.scale = moba_camera.top_down_size;
+            }
+        } else {
+            moba_camera.height = (moba_camera.height - notches * MOBA_ZOOM_STEP)
+                .clamp(MOBA_MIN_HEIGHT, MOBA_MAX_HEIGHT);
+        }
+    }
+
+    let look_ahead = moba_camera.look_ahead(camera_mode.view);
+    // how much faster it scrolls for how far it is zoomed out
+    let zoom = if top_down {
+        moba_camera.top_down_size / TOP_DOWN_DEFAULT_SIZE
+    } else {
+        moba_camera.height / ISOMETRIC_VIEWING_HEIGHT
+    };
+
+    let mut turn = 0.0;
+    if actions.just_pressed(Action::RotateViewLeft) {
+        turn += MOBA_ROTATION_STEP;
+    }
+    if actions.just_pressed(Action::RotateViewRight) {
+        turn -= MOBA_ROTATION_STEP;
+    }
+    let yaw = camera_rig.driver_mut::<YawPitch>().yaw_degrees;
+    let forward = Quat::from_rotation_y(yaw.to_radians()) * -Vec3::Z;
+    let yaw = if turn != 0.0 {
+        let snapped = (yaw / MOBA_ROTATION_STEP).round() * MOBA_ROTATION_STEP + turn;
+        camera_rig.driver_mut::<YawPitch>().yaw_degrees = snapped;
+        // keep looking at the same spot on the ground while turning
+        let turned = Quat::from_rotation_y(snapped.to_radians()) * -Vec3::Z;
+        camera_rig.driver_mut::<Position>().position += (forward - turned) * look_ahead;
+        snapped
+    } else {
+        yaw
+    };
+
+    let rotation = Quat::from_rotation_y(yaw.to_radians());
+    let forward = rotation * -Vec3::Z;
+    let right = rotation * Vec3::X;
+    let mut translation = Vec3::ZERO;
+    if let Some(window) = windows.get_primary() {
+        if let Some(cursor) = window.cursor_position() {
+            let mut scroll = Vec3::ZERO;
+            if cursor.x < EDGE_SCROLL_MARGIN {
+                scroll -= right;
+            }
+            if cursor.x > window.width() - EDGE_SCROLL_MARGIN {
+                scroll += right;
+            }
+            // the cursor position starts from the bottom of the window
+            if cursor.y < EDGE_SCROLL_MARGIN {
+                scroll -= forward;
+            }
+            if cursor.y > window.height() - EDGE_SCROLL_MARGIN {
+                scroll += forward;
+            }
+            let speed = EDGE_SCROLL_SPEED * zoom;
+            translation += scroll.normalize_or_zero() * speed * time.delta_seconds();
+        }
+
+        if actions.pressed(Action::DragPan) {
+            let mut mouse_delta = Vec2::ZERO;
+            for event in mouse_motion_events.iter() {
+                mouse_delta += event.delta;
+            }
+            // move the ground along with the cursor
+            let meters_per_pixel = if orthographic_projections.iter().next().is_some() {
+                2.0 * moba_camera.top_down_size / window.height()
+            } else {
+                let fov = match projections.iter().next() {
+                    Some(projection) => projection.fov,
+                    None => settings.fov(camera_mode.view).to_radians(),
+                };
+                let (height, pitch) = if top_down {
+                    (moba_camera.top_down_height(&settings), TOP_DOWN_VIEW_PITCH)
+                } else {
+                    (moba_camera.height, ISOMETRIC_VIEW_PITCH)
+                };
+                let distance = height / (-pitch).to_radians().sin();
+                2.0 * distance * (fov / 2.0).tan() / window.height()
+            };
+            translation += (forward * mouse_delta.y - right * mouse_delta.x) * meters_per_pixel;
+        }
+    }
+
+    let position = &mut camera_rig.driver_mut::<Position>().position;
+    *position += translation;
+    position.x = position.x.clamp(-MOBA_MAP_HALF_SIZE, MOBA_MAP_HALF_SIZE);
+    position.z = position.z.clamp(-MOBA_MAP_HALF_SIZE, MOBA_MAP_HALF_SIZE);
+    if !camera_mode.is_transitioning() {
+        position.y = if top_down {
+            moba_camera.top_down_height(&settings)
+        } else {
+            moba_camera.height
+        };
+    }
+}
+
+/// move the MOBA or top down camera to look at this spot on the ground,
+/// switching to the MOBA view from the views following the player
+pub(crate) fn look_at_ground(
+    target: Vec3,
+    fov: f32,
+    settings: &CameraSettings,
+    camera_mode: &mut CameraMode,
+    moba_camera: &MobaCamera,
+    camera_rig: &mut CameraRig,
+) {
+    if camera_mode.view != CameraView::Moba && camera_mode.view != CameraView::TopDown {
+        camera_mode.switch_to(
+            CameraView::Moba,
+            camera_rig,
+            fov,
+            None,
+            moba_camera,
+            settings,
+        );
+    }
+    let yaw = camera_rig.driver_mut::<YawPitch>().yaw_degrees;
+    let forward = Quat::from_rotation_y(yaw.to_radians()) * -Vec3::Z;
+    let focus = target - forward * moba_camera.look_ahead(camera_mode.view);
+    let position = &mut camera_rig.driver_mut::<Position>().position;
+    position.x = focus.x;
+    position.z = focus.z;
+}
+
+/// whether the camera is kept out of the terrain, turned off for the noclip debug mode
+pub struct CameraCollision {
+    pub enabled: bool,
+}
+
+/// move the camera rig by the motion, unless the collision is turned off it slides
+/// along the terrain it runs into and is kept above the ground
+pub(crate) fn move_camera(
+    camera_rig: &mut CameraRig,
+    motion: Vec3,
+    collision: &CameraCollision,
+    terrain: &Terrain,
+) {
+    let position = &mut camera_rig.driver_mut::<Position>().position;
+    if !collision.enabled {
+        *position += motion;
+        return;
+    }
+    let mut next = terrain.sweep_sphere(*position, CAMERA_COLLISION_RADIUS, motion);
+    let ray = Ray::new(next.into(), (-Vec3::Y).into());
+    if let Some(hit) = terrain.cast_ray(&ray, CAMERA_MIN_GROUND_HEIGHT) {
+        next.y += CAMERA_MIN_GROUND_HEIGHT - hit.toi;
+    }
+    *position = next;
+}
+
+pub(crate) fn toggle_noclip(actions: Res<ActionMap>, mut collision: ResMut<CameraCollision>) {
+    if actions.just_pressed(Action::Noclip) {
+        collision.enabled = !collision.enabled;
+    }
+}
+
+/// the third person camera orbiting around the player
+pub struct OrbitCamera {
+    rig: CameraRig,
+    /// in degrees
+    yaw: f32,
+    pitch: f32,
+    /// how far the player zoomed the camera out
+    distance: f32,
+    /// the distance after pulling in in front of the terrain that is in the way
+    current_distance: f32,
+}
+
+impl OrbitCamera {
+    pub fn new() -> Self {
+        OrbitCamera {
+            rig: CameraRig::builder()
+                .with(Position::new(dolly::glam::Vec3::ZERO))
+                .with(YawPitch::new().pitch_degrees(ORBIT_DEFAULT_PITCH))
+                .with(Arm::new(dolly::glam::Vec3::Z * ORBIT_DEFAULT_DISTANCE))
+                .with(LookAt::new(dolly::glam::Vec3::ZERO).tracking_smoothness(0.5))
+                .build(),
+            yaw: 0.0,
+            pitch: ORBIT_DEFAULT_PITCH,
+            distance: ORBIT_DEFAULT_DISTANCE,
+            current_distance: ORBIT_DEFAULT_DISTANCE,
+        }
+    }
+}
+
+/// orbit the camera around the player, zoom with the scroll wheel and pull the camera
+/// in when the terrain is in between it and the player
+pub(crate) fn orbit_camera(
+    time: Res<Time>,
+    actions: Res<ActionMap>,
+    settings: Res<CameraSettings>,
+    stick_mode: Res<StickMode>,
+    camera_mode: Res<CameraMode>,
+    terrain: Res<Terrain>,
+    mut mouse_motion_events: EventReader<MouseMotion>,
+    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut orbit_camera: ResMut<OrbitCamera>,
+    mut camera_rig: ResMut<CameraRig>,
+    players: Query<&Transform, (With<Player>, Without<DefaultCamera>)>,
+    mut query: Query<&mut Transform, With<DefaultCamera>>,
+) {
+    if camera_mode.view != CameraView::ThirdPerson {
+        return;
+    }
+    let player = match players.iter().next() {
+        Some(player) => player.translation,
+        None => return,
+    };
+    let time_delta_seconds: f32 = time.delta_seconds();
+
+    let mut mouse_delta = Vec2::ZERO;
+    if actions.pressed(Action::Look) {
+        for event in mouse_motion_events.iter() {
+            mouse_delta += event.delta;
+        }
+    }
+    let mut stick_look = Vec2::ZERO;
+    if *stick_mode == StickMode::Camera {
+        stick_look = actions.stick(Stick::Right) * settings.gamepad_look_speed * time_delta_seconds;
+    }
+    let mouse_look = settings.look_delta(mouse_delta) * settings.mouse_sensitivity;
+    let stick_look = settings.look_delta(stick_look);
+    orbit_camera.yaw = (orbit_camera.yaw - mouse_look.x - stick_look.x) % 360.0;
+    orbit_camera.pitch =
+        (orbit_camera.pitch - mouse_look.y + stick_look.y).clamp(ORBIT_MIN_PITCH, ORBIT_MAX_PITCH);
+
+    for event in mouse_wheel_events.iter() {
+        let notches = match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / PIXELS_PER_SCROLL_LINE,
+        };
+        orbit_camera.distance = (orbit_camera.distance - notches * ORBIT_ZOOM_STEP)
+            .clamp(ORBIT_MIN_DISTANCE, ORBIT_MAX_DISTANCE);
+    }
+
+    let pivot = player + Vec3::Y * ORBIT_PIVOT_HEIGHT;
+    let rotation = Quat::from_euler(
+        EulerRot::YXZ,
+        orbit_camera.yaw.to_radians(),
+        orbit_camera.pitch.to_radians(),
+        0.0,
+    );
+    let ray = Ray::new(pivot.into(), (rotation * Vec3::Z).into());
+    let distance = match terrain.cast_ray(&ray, orbit_camera.distance + ORBIT_CAMERA_RADIUS) {
+        Some(hit) => (hit.toi - ORBIT_CAMERA_RADIUS).max(ORBIT_MIN_PULL_IN),
+        None => orbit_camera.distance,
+    };
+    // pull in right away so the terrain never covers the player, then ease back out
+    orbit_camera.current_distance = if distance < orbit_camera.current_distance {
+        distance
+    } else {
+        let t = 1.0 - (-ORBIT_RELAX_RATE * time_delta_seconds).exp();
+        orbit_camera.current_distance + (distance - orbit_camera.current_distance) * t
+    };
+
+    let (yaw, pitch, current_distance) = (
+        orbit_camera.yaw,
+        orbit_camera.pitch,
+        orbit_camera.current_distance,
+    );
+    let rig = &mut orbit_camera.rig;
+    rig.driver_mut::<Position>().position = pivot;
+    let yaw_pitch = rig.driver_mut::<YawPitch>();
+    yaw_pitch.yaw_degrees = yaw;
+    yaw_pitch.pitch_degrees = pitch;
+    rig.driver_mut::<Arm>().offset = Vec3::Z * current_distance;
+    rig.driver_mut::<LookAt>().target = pivot;
+    let transform = rig.update(time_delta_seconds);
+
+    // the camera rig follows along so picking, chunk streaming and the
+    // transitions to the other views start from where the orbit camera is
+    camera_rig.driver_mut::<Position>().position = transform.position;
+    camera_rig
+        .driver_mut::<YawPitch>()
+        .set_rotation_quat(transform.rotation);
+    camera_rig.update(time_delta_seconds);
+    camera_rig.final_transform = transform;
+
+    let mut camera_transform = query.iter_mut().next().unwrap();
+    camera_transform.translation = transform.position;
+    camera_transform.rotation = transform.rotation;
+}
@@ -0,0 +1,168 @@
+use crate::camera::{CameraMode, CameraView, Easing};
+use crate::DefaultCamera;
+use bevy::prelude::*;
+use dolly::prelude::{CameraRig, Smooth};
+use serde::Deserialize;
+use std::time::SystemTime;
+
+/// the camera settings are loaded from this file and reloaded whenever it changes,
+/// the settings that are not in it keep their default
+const CAMERA_SETTINGS_FILE: &str = "assets/camera.ron";
+/// seconds in between checking whether the camera settings file changed
+const CAMERA_SETTINGS_POLL_INTERVAL: f32 = 1.0;
+
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct CameraSettings {
+    /// degrees the camera turns for each pixel the mouse moves
+    pub mouse_sensitivity: f32,
+    /// degrees per second the camera turns with the right stick fully tilted
+    pub gamepad_look_speed: f32,
+    /// look down when moving the mouse or the stick up
+    pub invert_y: bool,
+    /// meters per second the free camera flies
+    pub move_speed: f32,
+    /// meters per second the camera pans with the pan actions
+    pub pan_speed: f32,
+    /// how many times faster the free camera flies while boosting, and slower while going slow
+    pub boost_factor: f32,
+    /// how smoothly the camera catches up with where it is moved and turned, 0 is right away
+    pub position_smoothness: f32,
+    pub rotation_smoothness: f32,
+    /// field of view of each of the views, in degrees
+    pub fps_fov: f32,
+    pub moba_fov: f32,
+    pub third_person_fov: f32,
+    /// the view the camera starts in
+    pub default_view: CameraView,
+    /// seconds it takes to go from one camera view to the other
+    pub transition_duration: f32,
+    /// how the camera view transitions speed up and slow down
+    pub transition_easing: Easing,
+}
+
+impl Default for CameraSettings {
+    fn default() -> Self {
+        CameraSettings {
+            mouse_sensitivity: 0.05,
+            gamepad_look_speed: 120.0,
+            invert_y: false,
+            move_speed: 2.5,
+            pan_speed: 25.0,
+            boost_factor: 10.0,
+            position_smoothness: 1.0,
+            rotation_smoothness: 1.0,
+            fps_fov: 45.0,
+            moba_fov: 30.0,
+            third_person_fov: 60.0,
+            default_view: CameraView::Fps,
+            transition_duration: 0.6,
+            transition_easing: Easing::EaseInOut,
+        }
+    }
+}
+
+impl CameraSettings {
+    /// the field of view of this view, in degrees
+    pub fn fov(&self, view: CameraView) -> f32 {
+        match view {
+            CameraView::Fps => self.fps_fov,
+            CameraView::Moba => self.moba_fov,
+            CameraView::ThirdPerson => self.third_person_fov,
+            // the top down view is orthographic, it only looks through the
+            // MOBA field of view while transitioning to and from it
+            CameraView::TopDown => self.moba_fov,
+        }
+    }
+
+    /// how many degrees to turn for this much mouse motion or stick look
+    pub fn look_delta(&self, delta: Vec2) -> Vec2 {
+        let y = if self.invert_y { -delta.y } else { delta.y };
+        Vec2::new(delta.x, y)
+    }
+}
+
+fn read_camera_settings() -> Result<CameraSettings, String> {
+    std::fs::read_to_string(CAMERA_SETTINGS_FILE)
+        .map_err(|e| e.to_string())
+        .and_then(|content| ron::from_str(&content).map_err(|e| e.to_string()))
+}
+
+fn modified_time() -> Option<SystemTime> {
+    std::fs::metadata(CAMERA_SETTINGS_FILE)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// keeps track of when the camera settings file was last loaded
+pub struct CameraSettingsWatcher {
+    modified: Option<SystemTime>,
+    timer: Timer,
+}
+
+/// load the camera settings, this runs before the camera is set up
+pub(crate) fn setup(mut commands: Commands) {
+    let settings = match read_camera_settings() {
+        Ok(settings) => settings,
+        Err(e) => {
+            warn!(
+                "unable to load {}: {}, using the default camera settings",
+                CAMERA_SETTINGS_FILE, e
+            );
+            CameraSettings::default()
+        }
+    };
+    commands.insert_resource(settings);
+    commands.insert_resource(CameraSettingsWatcher {
+        modified: modified_time(),
+        timer: Timer::from_seconds(CAMERA_SETTINGS_POLL_INTERVAL, true),
+    });
+}
+
+/// load the camera settings again when the file changed, keeping the current
+/// settings when the file can not be loaded
+pub(crate) fn reload_camera_settings(
+    time: Res<Time>,
+    mut watcher: ResMut<CameraSettingsWatcher>,
+    mut settings: ResMut<CameraSettings>,
+) {
+    if !watcher.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let modified = modified_time();
+    if modified == watcher.modified {
+        return;
+    }
+    watcher.modified = modified;
+    match read_camera_settings() {
+        Ok(reloaded) => {
+            *settings = reloaded;
+            info!("reloaded {}", CAMERA_SETTINGS_FILE);
+        }
+        Err(e) => warn!(
+            "unable to reload {}: {}, keeping the current camera settings",
+            CAMERA_SETTINGS_FILE, e
+        ),
+    }
+}
+
+/// apply the settings that are not read every frame by the camera systems
+pub(crate) fn apply_camera_settings(
+    settings: Res<CameraSettings>,
+    camera_mode: Res<CameraMode>,
+    mut camera_rig: ResMut<CameraRig>,
+    mut projections: Query<&mut PerspectiveProjection, With<DefaultCamera>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    let smooth = camera_rig.driver_mut::<Smooth>();
+    smooth.position_smoothness = settings.position_smoothness;
+    smooth.rotation_smoothness = settings.rotation_smoothness;
+    // the transition animates the field of view to the new one on its own
+    if !camera_mode.is_transitioning() {
+        for mut projection in projections.iter_mut() {
+            projection.fov = settings.fov(camera_mode.view).to_radians();
+        }
+    }
+}
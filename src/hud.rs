@@ -1,32 +1,72 @@
-use crate::selector;
+use crate::actions::{Action, ActionMap};
+use crate::camera::{CameraMode, CameraView};
+use crate::camera_settings::CameraSettings;
+use crate::mesh_collider::MeshCollider;
+use crate::picking::{PickEvent, PickLayer, Pickable, Picking};
+use crate::weapon::Weapons;
+use crate::DefaultCamera;
 use bevy::{math::Vec3, prelude::*};
-use dolly::rig::CameraRig;
-use parry3d::{
-    math::{Real, Vector},
-    query::{Ray, RayCast, RayIntersection},
-    shape::Cuboid,
-};
+
+/// where the HUD sits in front of the camera
+const HUD_TRANSLATION: Vec3 = bevy::math::const_vec3!([-0.09, -0.03, -0.2]);
+/// the buttons of the hotbar are in a grid of this many rows and columns
+const HOTBAR_SIZE: usize = 4;
+/// the action of each button of the hotbar, row by row from the top left,
+/// the number keys press the first ten of them
+const HOTBAR: [Option<Action>; HOTBAR_SIZE * HOTBAR_SIZE] = [
+    Some(Action::WeaponSlot(0)),
+    Some(Action::WeaponSlot(1)),
+    Some(Action::WeaponSlot(2)),
+    Some(Action::WeaponSlot(3)),
+    Some(Action::WeaponSlot(4)),
+    Some(Action::WeaponSlot(5)),
+    Some(Action::WeaponSlot(6)),
+    Some(Action::WeaponSlot(7)),
+    Some(Action::WeaponSlot(8)),
+    Some(Action::SelectTool),
+    Some(Action::ToggleCameraView),
+    Some(Action::FpsView),
+    Some(Action::MobaView),
+    Some(Action::ThirdPersonView),
+    Some(Action::TopDownView),
+    None,
+];
 
 #[derive(Component, Copy, Clone)]
-struct Hud;
+pub struct Hud;
 
+/// a button of the hotbar, clicking it or pressing its number key does its action
 #[derive(Component, Copy, Clone)]
 pub struct UiButton {
-    half_extents: Vec3, //describe by halfextents to each access
+    pub action: Option<Action>,
+    /// where the button is in the hotbar, its number key presses it through `Action::Hotbar`
+    pub slot: usize,
+    /// held down with the cursor, until it is let go
+    pressed: bool,
+}
+
+/// the look of the hotbar buttons in each of their states
+pub struct ButtonMaterials {
+    normal: Handle<StandardMaterial>,
+    hovered: Handle<StandardMaterial>,
+    pressed: Handle<StandardMaterial>,
+    disabled: Handle<StandardMaterial>,
 }
 
-impl RayCast for UiButton {
-    fn cast_local_ray_and_get_normal(
-        &self,
-        ray: &Ray,
-        max_toi: Real,
-        solid: bool,
-    ) -> Option<RayIntersection> {
-        Cuboid::new(self.half_extents.into()).cast_local_ray_and_get_normal(
-            &ray,
-            f32::INFINITY,
-            true,
-        )
+impl ButtonMaterials {
+    pub fn new(materials: &mut Assets<StandardMaterial>) -> Self {
+        let mut material = |color| {
+            materials.add(StandardMaterial {
+                base_color: color,
+                ..Default::default()
+            })
+        };
+        ButtonMaterials {
+            normal: material(Color::RED),
+            hovered: material(Color::rgb(1.0, 0.5, 0.5)),
+            pressed: material(Color::YELLOW),
+            disabled: material(Color::DARK_GRAY),
+        }
     }
 }
 
@@ -34,6 +74,7 @@ pub(crate) fn setup(
     parent: &mut ChildBuilder,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
+    button_materials: &ButtonMaterials,
 ) {
     parent
         .spawn_bundle(PbrBundle {
@@ -43,19 +84,23 @@ pub(crate) fn setup(
                 ..Default::default()
             }),
             transform: Transform {
-                translation: Vec3::new(-0.09, -0.03, -0.2),
+                translation: HUD_TRANSLATION,
                 //rotation: Quat::from_rotation_y(20.0_f32.to_radians()),
                 ..Default::default()
             },
             ..Default::default()
         })
         .insert(Hud)
+        // clicking in between the buttons is still on the HUD
+        .insert(MeshCollider::trimesh())
+        .insert(Pickable::new(PickLayer::Ui))
         .with_children(|ui| {
-            for j in 1..5 {
-                for i in 1..5 {
+            for j in 1..=HOTBAR_SIZE {
+                for i in 1..=HOTBAR_SIZE {
                     let location =
                         Vec3::new(i as f32 * 0.02 - 0.05, j as f32 * 0.02 - 0.05, 0.0001);
-                    let half_extents = Vec3::new(0.005, 0.01, 0.0);
+                    // the rows go up from the bottom, the slots start from the top left
+                    let slot = (HOTBAR_SIZE - j) * HOTBAR_SIZE + i - 1;
 
                     ui.spawn_bundle(PbrBundle {
                         mesh: meshes.add(Mesh::from(shape::Box::new(0.01, 0.01, 0.0001))),
@@ -63,33 +108,120 @@ pub(crate) fn setup(
                             translation: location,
                             ..Default::default()
                         },
-                        material: materials.add(StandardMaterial {
-                            base_color: Color::RED,
-                            ..Default::default()
-                        }),
+                        material: button_materials.normal.clone(),
                         ..Default::default()
                     })
-                    .insert(UiButton { half_extents });
+                    .insert(UiButton {
+                        action: HOTBAR[slot],
+                        slot,
+                        pressed: false,
+                    })
+                    .insert(MeshCollider::trimesh())
+                    .insert(Pickable::new(PickLayer::Ui));
                 }
             }
         });
 }
 
-pub(crate) fn button_undercursor(
-    cubes: Query<(&GlobalTransform, &UiButton)>,
-    keys: Res<Input<KeyCode>>,
-    mouse_buttons: Res<Input<MouseButton>>,
-    windows: Res<Windows>,
-    camera_rig: Res<CameraRig>,
-    camera: Query<&Camera>,
+/// the orthographic camera shows the same size no matter how far away, so the HUD is scaled up
+/// to cover as much of the window as it does in front of the FPS camera
+pub(crate) fn fit_hud(
+    settings: Res<CameraSettings>,
+    projections: Query<&OrthographicProjection, With<DefaultCamera>>,
+    mut huds: Query<&mut Transform, With<Hud>>,
+) {
+    let scale = match projections.iter().next() {
+        Some(projection) => {
+            let half_height = -HUD_TRANSLATION.z * (settings.fps_fov.to_radians() / 2.0).tan();
+            projection.scale / half_height
+        }
+        None => 1.0,
+    };
+    for mut transform in huds.iter_mut() {
+        transform.translation = Vec3::new(
+            HUD_TRANSLATION.x * scale,
+            HUD_TRANSLATION.y * scale,
+            HUD_TRANSLATION.z,
+        );
+        transform.scale = Vec3::splat(scale);
+    }
+}
+
+/// whether the action of the button can be done right now
+fn is_enabled(action: Option<Action>, weapons: &Weapons, camera_mode: &CameraMode) -> bool {
+    match action {
+        Some(Action::WeaponSlot(slot)) => weapons
+            .slots
+            .get(slot)
+            .map_or(false, |slot| slot.ammo != Some(0)),
+        Some(Action::FpsView) => camera_mode.view != CameraView::Fps,
+        Some(Action::MobaView) => camera_mode.view != CameraView::Moba,
+        Some(Action::ThirdPersonView) => camera_mode.view != CameraView::ThirdPerson,
+        Some(Action::TopDownView) => camera_mode.view != CameraView::TopDown,
+        Some(_) => true,
+        None => false,
+    }
+}
+
+/// do the action of the hotbar button clicked or pressed with its number key,
+/// the action is triggered for the next frame as if its own key was pressed
+pub(crate) fn press_buttons(
+    mut pick_events: EventReader<PickEvent>,
+    mut actions: ResMut<ActionMap>,
+    weapons: Res<Weapons>,
+    camera_mode: Res<CameraMode>,
+    mut buttons: Query<(Entity, &mut UiButton)>,
+) {
+    let mut clicked = vec![];
+    for event in pick_events.iter() {
+        match event {
+            PickEvent::Pressed(hit) => {
+                if let Ok((_, mut button)) = buttons.get_mut(hit.entity) {
+                    button.pressed = true;
+                    clicked.push(hit.entity);
+                }
+            }
+            PickEvent::Released(hit) => {
+                if let Ok((_, mut button)) = buttons.get_mut(hit.entity) {
+                    button.pressed = false;
+                }
+            }
+            _ => (),
+        }
+    }
+    for (entity, button) in buttons.iter() {
+        let pressed =
+            clicked.contains(&entity) || actions.just_pressed(Action::Hotbar(button.slot));
+        if pressed && is_enabled(button.action, &weapons, &camera_mode) {
+            if let Some(action) = button.action {
+                actions.trigger(action);
+            }
+        }
+    }
+}
+
+/// show whether the hotbar buttons are disabled, pressed or under the cursor
+pub(crate) fn update_button_materials(
+    actions: Res<ActionMap>,
+    picking: Res<Picking>,
+    weapons: Res<Weapons>,
+    camera_mode: Res<CameraMode>,
+    button_materials: Res<ButtonMaterials>,
+    mut buttons: Query<(Entity, &UiButton, &mut Handle<StandardMaterial>)>,
 ) {
-    if mouse_buttons.pressed(MouseButton::Left) {
-        if let Some((cube_index, hit)) =
-            selector::component_under_cursory_ray(cubes, windows, camera_rig, camera)
-        {
-            println!("selected ui: {} at: {}", cube_index, hit);
+    let hovered = picking.hovered().map(|hit| hit.entity);
+    for (entity, button, mut material) in buttons.iter_mut() {
+        let state = if !is_enabled(button.action, &weapons, &camera_mode) {
+            &button_materials.disabled
+        } else if button.pressed || actions.pressed(Action::Hotbar(button.slot)) {
+            &button_materials.pressed
+        } else if hovered == Some(entity) {
+            &button_materials.hovered
         } else {
-            println!("No hit..");
+            &button_materials.normal
+        };
+        if *material != *state {
+            *material = state.clone();
         }
     }
 }
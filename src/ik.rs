@@ -1,6 +1,8 @@
-use crate::selector;
+use crate::actions::{Action, ActionMap, Stick, StickMode};
+use crate::mesh_collider::MeshCollider;
+use crate::mining::{self, MiningTool};
+use crate::picking::{PickEvent, PickLayer, Pickable};
 use bevy::{math::Quat, prelude::*};
-use dolly::rig::CameraRig;
 use k::{
     connect,
     nalgebra::{Isometry3, Translation3, UnitQuaternion, Vector3},
@@ -8,42 +10,20 @@ use k::{
     JacobianIkSolver, JointType, NodeBuilder, SerialChain,
 };
 use nalgebra::Unit;
-use parry3d::{
-    math::{Point, Real, Vector},
-    query::{Ray, RayCast, RayIntersection},
-    shape::Cuboid,
-};
 use std::collections::HashMap;
 
 const DEFAULT_ANGLES: &[f32] = &[0.2, 0.2, 0.0, -1.5, 0.0, -0.3, 0.0];
+const IK_CUBE_SIZE: f32 = 0.2;
+/// the glow of the IK cubes, brighter while the cursor is over them
+const IK_CUBE_EMISSIVE: Color = Color::RED;
+const IK_CUBE_HOVER_EMISSIVE: Color = Color::ORANGE;
 
 #[derive(Component)]
-pub struct IkCubes {
-    half_extents: Vec3,
-}
-
-impl RayCast for IkCubes {
-    fn cast_local_ray_and_get_normal(
-        &self,
-        ray: &Ray,
-        max_toi: Real,
-        solid: bool,
-    ) -> Option<RayIntersection> {
-        Cuboid::new(Vector::new(
-            self.half_extents.x,
-            self.half_extents.y,
-            self.half_extents.z,
-        ))
-        .cast_local_ray_and_get_normal(&ray, f32::INFINITY, true)
-    }
-}
+pub struct IkCubes;
 
 #[derive(Default, Debug)]
 pub struct IkCubeTargetLocation(Option<Vec3>);
 
-#[derive(Default, Debug)]
-pub struct IkHitImpact(pub Option<Vec3>);
-
 #[derive(Default, Debug)]
 pub struct SelectedIkCube(Option<usize>);
 
@@ -152,58 +132,94 @@ pub(crate) fn setup(
     let arm = build_arm();
     let arm_len = arm.iter().count();
     commands.insert_resource(arm);
-    commands.insert_resource(IkHitImpact::default());
     commands.insert_resource(SelectedIkCube::default());
     commands.insert_resource(IkCubeTargetLocation::default());
 
     for i in 0..arm_len {
-        commands
-            .spawn_bundle(PbrBundle {
-                mesh: meshes.add(Mesh::from(shape::Cube { size: 0.2 })),
-                material: materials.add(StandardMaterial {
-                    base_color: Color::RED,
-                    metallic: 1.0,
-                    perceptual_roughness: 0.0,
-                    reflectance: 1.0,
-                    emissive: Color::RED,
-                    ..Default::default()
-                }),
-                transform: Transform {
-                    translation: Vec3::new(i as f32, 1.0, 0.0),
-                    ..Default::default()
-                },
+        let mut cube = commands.spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(shape::Cube { size: IK_CUBE_SIZE })),
+            material: materials.add(StandardMaterial {
+                base_color: Color::RED,
+                metallic: 1.0,
+                perceptual_roughness: 0.0,
+                reflectance: 1.0,
+                emissive: IK_CUBE_EMISSIVE,
                 ..Default::default()
-            })
-            .insert(IkCubes {
-                half_extents: Vec3::new(0.1, 0.1, 0.1),
+            }),
+            transform: Transform {
+                translation: Vec3::new(i as f32, 1.0, 0.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        });
+        cube.insert(IkCubes)
+            .insert(MeshCollider::convex_hull())
+            .insert(Pickable::new(PickLayer::Gizmo));
+
+        // the last cube is the end effector which holds the mining tool
+        if i == arm_len - 1 {
+            cube.insert(MiningTool::default()).with_children(|parent| {
+                mining::spawn_tool(parent, &mut meshes, &mut materials);
             });
+        }
     }
 }
 
-pub fn ik_box_undercursor(
-    mut commands: Commands,
-    cubes: Query<(&GlobalTransform, &IkCubes)>,
-    keys: Res<Input<KeyCode>>,
-    mouse_buttons: Res<Input<MouseButton>>,
-    windows: Res<Windows>,
+/// click an IK cube to select it and drag it around, or click the terrain
+/// to move the selected cube onto the surface there
+pub fn pick_ik_cubes(
+    mut pick_events: EventReader<PickEvent>,
+    cubes: Query<Entity, With<IkCubes>>,
     mut selected_cube: ResMut<SelectedIkCube>,
-    camera_rig: Res<CameraRig>,
-    mut hit_impact: ResMut<IkHitImpact>,
-    camera: Query<&Camera>,
+    mut cube_target_location: ResMut<IkCubeTargetLocation>,
 ) {
-    if !keys.pressed(KeyCode::LAlt) && mouse_buttons.pressed(MouseButton::Left) {
-        if let Some((cube_index, hit)) =
-            selector::component_under_cursory_ray(cubes, windows, camera_rig, camera)
+    let cube_index = |entity: Entity| cubes.iter().position(|cube| cube == entity);
+    for event in pick_events.iter() {
+        match event {
+            PickEvent::Pressed(hit)
+                if hit.layer == PickLayer::Terrain && selected_cube.get().is_some() =>
+            {
+                cube_target_location.0 = Some(hit.point + hit.normal * IK_CUBE_SIZE / 2.0);
+            }
+            PickEvent::Pressed(hit) | PickEvent::DragStart(hit) => {
+                if let Some(index) = cube_index(hit.entity) {
+                    selected_cube.set_selected(index);
+                }
+            }
+            PickEvent::Drag(hit) | PickEvent::DragEnd(hit) if cube_index(hit.entity).is_some() => {
+                cube_target_location.0 = Some(hit.point);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// light up the IK cube under the cursor
+pub fn highlight_ik_cubes(
+    mut pick_events: EventReader<PickEvent>,
+    cubes: Query<&Handle<StandardMaterial>, With<IkCubes>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for event in pick_events.iter() {
+        let (hit, emissive) = match event {
+            PickEvent::HoverEnter(hit) => (hit, IK_CUBE_HOVER_EMISSIVE),
+            PickEvent::HoverExit(hit) => (hit, IK_CUBE_EMISSIVE),
+            _ => continue,
+        };
+        if let Some(material) = cubes
+            .get(hit.entity)
+            .ok()
+            .and_then(|material| materials.get_mut(material))
         {
-            selected_cube.set_selected(cube_index);
-            hit_impact.0 = Some(hit);
+            material.emissive = emissive;
         }
     }
 }
 
 pub fn solve(
     time: Res<Time>,
-    keys: Res<Input<KeyCode>>,
+    actions: Res<ActionMap>,
+    stick_mode: Res<StickMode>,
     arm: ResMut<SerialChain<f32>>,
     mut cubes: Query<&mut Transform, With<IkCubes>>,
 ) {
@@ -215,36 +231,44 @@ pub fn solve(
     let multiplier = 1.0;
     let mut reset = false;
 
-    if keys.pressed(KeyCode::R) {
+    if actions.pressed(Action::IkReset) {
         reset = true;
         arm.set_joint_positions(&DEFAULT_ANGLES).unwrap();
         arm.update_transforms();
     }
 
-    if !keys.pressed(KeyCode::LShift) && keys.pressed(KeyCode::X) {
+    if actions.pressed(Action::IkMoveXPositive) {
         move_vec.x += 1.0;
     }
 
-    if keys.pressed(KeyCode::LShift) && keys.pressed(KeyCode::X) {
+    if actions.pressed(Action::IkMoveXNegative) {
         move_vec.x -= 1.0;
     }
 
-    if !keys.pressed(KeyCode::LShift) && keys.pressed(KeyCode::Y) {
+    if actions.pressed(Action::IkMoveYPositive) {
         move_vec.y += 1.0;
     }
 
-    if keys.pressed(KeyCode::LShift) && keys.pressed(KeyCode::Y) {
+    if actions.pressed(Action::IkMoveYNegative) {
         move_vec.y -= 1.0;
     }
 
-    if !keys.pressed(KeyCode::LShift) && keys.pressed(KeyCode::Z) {
+    if actions.pressed(Action::IkMoveZPositive) {
         move_vec.z += 1.0;
     }
 
-    if keys.pressed(KeyCode::LShift) && keys.pressed(KeyCode::Z) {
+    if actions.pressed(Action::IkMoveZNegative) {
         move_vec.z -= 1.0;
     }
 
+    if *stick_mode == StickMode::IkArm {
+        let left_stick = actions.stick(Stick::Left);
+        let right_stick = actions.stick(Stick::Right);
+        move_vec.x += left_stick.x;
+        move_vec.z -= left_stick.y;
+        move_vec.y += right_stick.y;
+    }
+
     target.translation.vector.x += move_vec.x * time_delta_seconds * multiplier;
     target.translation.vector.y += move_vec.y * time_delta_seconds * multiplier;
     target.translation.vector.z += move_vec.z * time_delta_seconds * multiplier;
@@ -274,25 +298,19 @@ pub fn solve(
     }
 }
 
-pub fn command_move_selected_ik_object(
-    mut commands: Commands,
-    mouse_buttons: Res<Input<MouseButton>>,
-    selected_cube: Res<SelectedIkCube>,
-    mut cube_target_location: ResMut<IkCubeTargetLocation>,
-    keys: Res<Input<KeyCode>>,
-    windows: Res<Windows>,
-    camera_rig: Res<CameraRig>,
-    camera: Query<&Camera>,
-    hit_impact: Res<IkHitImpact>,
+/// select the IK cube holding the mining tool, so it can be moved around right away
+pub fn select_tool(
+    actions: Res<ActionMap>,
+    cubes: Query<Entity, With<IkCubes>>,
+    tools: Query<(), (With<IkCubes>, With<MiningTool>)>,
+    mut selected_cube: ResMut<SelectedIkCube>,
 ) {
-    if keys.pressed(KeyCode::LAlt) && mouse_buttons.pressed(MouseButton::Left) {
-        let camera_location = camera_rig.final_transform.position;
-        let ray = selector::cursor_ray(windows, camera, camera_rig);
-        if let Some(cube_index) = selected_cube.get() {
-            let hit_impact = hit_impact.0.unwrap();
-            if let Some(hit) = selector::intersect_half_space(ray, hit_impact, camera_location) {
-                cube_target_location.0 = Some(hit);
-            }
+    if !actions.just_pressed(Action::SelectTool) {
+        return;
+    }
+    for (cube_index, cube) in cubes.iter().enumerate() {
+        if tools.get(cube).is_ok() {
+            selected_cube.set_selected(cube_index);
         }
     }
 }
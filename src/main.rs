@@ -1,6 +1,14 @@
-use bevy::input::mouse::MouseMotion;
+use actions::{Action, ActionMap, Stick, StickMode};
+use bevy::input::{mouse::MouseMotion, InputSystem};
 use bevy::prelude::*;
+use broad_phase::{BroadPhasePlugin, BroadPhaseUpdate};
+use camera::{CameraCollision, CameraMode, CameraView, MobaCamera, OrbitCamera};
+use camera_settings::CameraSettings;
 use dolly::prelude::{CameraRig, Position, Smooth, YawPitch};
+use mesh_collider::{MeshCollider, MeshColliderPlugin};
+use picking::{PickablePlugin, PickingPlugin, PickingSystem};
+use player::PlayerController;
+use terrain::Terrain;
 
 const ISOMETRIC_VIEW_YAW: f32 = 0.0;
 const ISOMETRIC_VIEW_PITCH: f32 = -60.0;
@@ -8,55 +16,103 @@ const ISOMETRIC_VIEWING_HEIGHT: f32 = 20.0; //20m vantage point
 
 const FPS_VIEW_YAW: f32 = 0.0;
 const FPS_VIEW_PITCH: f32 = 0.0;
-const FPS_VIEWING_HEIGHT: f32 = 2.0; // height of the model
+const FPS_VIEWING_HEIGHT: f32 = 1.7; // eye height of the player
 
-/// flat whether to use iso metric view like in popular MOBAs
-/// otherwise use first person shooter
-const USE_ISOMETRIC_VIEW: bool = false;
+const TOP_DOWN_VIEW_PITCH: f32 = -90.0; // looking straight down
 
+mod actions;
+mod broad_phase;
+mod camera;
+mod camera_settings;
 mod hud;
 mod ik;
+mod mesh_collider;
+mod minimap;
+mod mining;
+mod picking;
+mod player;
 mod projectile;
 mod selector;
+mod terrain;
+mod weapon;
+mod worldgen;
 
 fn main() {
     App::new()
         .add_plugins(DefaultPlugins)
+        .add_plugin(MeshColliderPlugin)
+        .add_plugin(BroadPhasePlugin::<MeshCollider>::default())
+        .add_plugin(PickingPlugin)
+        .add_plugin(PickablePlugin::<MeshCollider>::default())
+        .add_startup_system(actions::setup)
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            actions::update_actions.after(InputSystem),
+        )
         .add_startup_system(setup)
+        .add_startup_system_to_stage(StartupStage::PreStartup, camera_settings::setup)
         .add_startup_system(setup_camera)
+        .add_startup_system(terrain::setup)
+        .add_startup_system_to_stage(StartupStage::PostStartup, player::setup)
+        .add_system(terrain::stream_chunks)
+        .add_system(terrain::remesh_chunks)
+        .add_system(
+            terrain::pick_terrain
+                .label(PickingSystem::Cast)
+                .after(PickingSystem::CursorRay),
+        )
+        .add_system(actions::toggle_stick_mode)
         .add_system(fps_camera)
         .add_system(pan_camera)
-        .add_system(change_camera_mode)
-        .add_system(hud::button_undercursor)
+        .add_system(camera::change_camera_mode)
+        .add_system(camera::animate_camera_mode)
+        .add_system(camera::orbit_camera)
+        .add_system(camera::moba_camera)
+        .add_system(camera::toggle_noclip)
+        .add_system(camera_settings::reload_camera_settings)
+        .add_system(camera_settings::apply_camera_settings)
+        .add_system(player::move_player)
+        .add_system(hud::fit_hud)
+        .add_system(hud::press_buttons.after(PickingSystem::Events))
+        .add_system(hud::update_button_materials.after(PickingSystem::Events))
         .add_startup_system(ik::setup)
-        .add_system(ik::ik_box_undercursor)
+        .add_system(ik::pick_ik_cubes.after(PickingSystem::Events))
+        .add_system(ik::highlight_ik_cubes.after(PickingSystem::Events))
+        .add_system(ik::select_tool)
         .add_system(ik::solve)
-        .add_system(ik::command_move_selected_ik_object)
         .add_system(ik::update_move_selected_ik_object)
-        .add_system(projectile::spawn_projectile)
-        .add_system(projectile::move_projectile)
+        .add_startup_system(mining::setup)
+        .add_system(mining::mine_terrain)
+        .add_system(mining::drop_items)
+        .add_system(mining::pickup_items)
+        .add_startup_system(minimap::setup)
+        .add_system(minimap::refresh_minimap)
+        .add_system(minimap::update_minimap_icons)
+        .add_system(
+            minimap::pick_minimap
+                .label(PickingSystem::Cast)
+                .after(PickingSystem::CursorRay),
+        )
+        .add_system(minimap::click_minimap.after(PickingSystem::Events))
+        .add_startup_system(projectile::setup)
+        .add_startup_system(weapon::setup)
+        .add_system(weapon::switch_weapon)
+        .add_system(projectile::draw_trajectory)
+        .add_system(
+            projectile::spawn_projectile
+                .after(BroadPhaseUpdate)
+                .after(PickingSystem::Events),
+        )
+        .add_event::<projectile::ProjectileImpact>()
+        .add_system(projectile::move_projectile.after(BroadPhaseUpdate))
+        .add_system(projectile::explode_projectile)
         .run();
 }
 
 #[derive(Component)]
 struct DefaultCamera;
 
-fn setup(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    // ground plane
-    commands.spawn_bundle(PbrBundle {
-        mesh: meshes.add(Mesh::from(shape::Plane { size: 100.0 })),
-        material: materials.add(StandardMaterial {
-            base_color: Color::WHITE,
-            perceptual_roughness: 1.0,
-            ..Default::default()
-        }),
-        ..Default::default()
-    });
-
+fn setup(mut commands: Commands) {
     // directional 'sun' light
     const HALF_SIZE: f32 = 10.0;
     commands.spawn_bundle(DirectionalLightBundle {
@@ -83,37 +139,48 @@ fn setup(
     });
 }
 
-fn setup_camera_resource(mut query: Query<&Camera>) {
-    for camera in query.iter() {
-        println!("camera matrix: {}", camera.projection_matrix);
-    }
-}
-
 fn setup_camera(
     mut commands: Commands,
+    settings: Res<CameraSettings>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    commands
+    let camera_mode = CameraMode::new(settings.default_view);
+    let moba_camera = MobaCamera::new();
+    let button_materials = hud::ButtonMaterials::new(&mut materials);
+
+    let camera = commands
         .spawn_bundle(PerspectiveCameraBundle {
             transform: Transform::from_xyz(0., 2.5, 10.).looking_at(Vec3::ZERO, Vec3::Y),
+            perspective_projection: PerspectiveProjection {
+                fov: settings.fov(camera_mode.view).to_radians(),
+                ..Default::default()
+            },
             ..Default::default()
         })
         .insert(DefaultCamera)
         .with_children(|parent| {
-            hud::setup(parent, meshes, materials);
-        });
-
-    let (yaw, pitch) = if USE_ISOMETRIC_VIEW {
-        (ISOMETRIC_VIEW_YAW, ISOMETRIC_VIEW_PITCH)
-    } else {
-        (FPS_VIEW_YAW, FPS_VIEW_PITCH)
-    };
+            hud::setup(parent, meshes, materials, &button_materials);
+        })
+        .id();
+    if camera_mode.view == CameraView::TopDown {
+        camera::use_orthographic(&mut commands, camera, moba_camera.top_down_size);
+    }
 
-    let viewing_height = if USE_ISOMETRIC_VIEW {
-        ISOMETRIC_VIEWING_HEIGHT
-    } else {
-        FPS_VIEWING_HEIGHT
+    let (yaw, pitch, viewing_height) = match camera_mode.view {
+        CameraView::Moba => (
+            ISOMETRIC_VIEW_YAW,
+            ISOMETRIC_VIEW_PITCH,
+            ISOMETRIC_VIEWING_HEIGHT,
+        ),
+        CameraView::Fps | CameraView::ThirdPerson => {
+            (FPS_VIEW_YAW, FPS_VIEW_PITCH, FPS_VIEWING_HEIGHT)
+        }
+        CameraView::TopDown => (
+            ISOMETRIC_VIEW_YAW,
+            TOP_DOWN_VIEW_PITCH,
+            moba_camera.top_down_height(&settings),
+        ),
     };
 
     // Not required, just a nice camera driver to give easy, smooth, camera controls.
@@ -124,147 +191,152 @@ fn setup_camera(
             4.0,
         )))
         .with(YawPitch::new().yaw_degrees(yaw).pitch_degrees(pitch))
-        .with(Smooth::new_position_rotation(1.0, 1.0))
+        .with(Smooth::new_position_rotation(
+            settings.position_smoothness,
+            settings.rotation_smoothness,
+        ))
         .build();
 
     commands.insert_resource(camera_rig);
+    commands.insert_resource(camera_mode);
+    commands.insert_resource(OrbitCamera::new());
+    commands.insert_resource(moba_camera);
+    commands.insert_resource(button_materials);
+    commands.insert_resource(CameraCollision { enabled: true });
 }
 
 fn fps_camera(
     time: Res<Time>,
-    keys: Res<Input<KeyCode>>,
+    actions: Res<ActionMap>,
+    settings: Res<CameraSettings>,
+    stick_mode: Res<StickMode>,
+    camera_mode: Res<CameraMode>,
+    collision: Res<CameraCollision>,
+    terrain: Res<Terrain>,
     mut mouse_motion_events: EventReader<MouseMotion>,
-    mouse_buttons: Res<Input<MouseButton>>,
     mut camera_rig: ResMut<CameraRig>,
+    players: Query<(&Transform, &PlayerController), Without<DefaultCamera>>,
     mut query: Query<&mut Transform, With<DefaultCamera>>,
 ) {
+    // the orbit camera moves the camera while following the player
+    if camera_mode.view == CameraView::ThirdPerson {
+        return;
+    }
+    let walking = camera_mode.follows_player(&collision);
+    // the top down view keeps looking straight down and only pans along the ground
+    let flying = !walking && camera_mode.view != CameraView::TopDown;
     let time_delta_seconds: f32 = time.delta_seconds();
 
     let mut move_vec = Vec3::ZERO;
     let mut boost = 0.0;
 
-    if keys.pressed(KeyCode::LShift) {
+    if actions.pressed(Action::Boost) {
         boost = 1.0;
     }
-    if keys.pressed(KeyCode::LControl) {
+    if actions.pressed(Action::Slow) {
         boost = -1.0;
     }
 
-    if keys.pressed(KeyCode::W) {
+    if actions.pressed(Action::MoveForward) {
         move_vec.z -= 1.0;
     }
-    if keys.pressed(KeyCode::S) {
+    if actions.pressed(Action::MoveBackward) {
         move_vec.z += 1.0;
     }
-    if keys.pressed(KeyCode::A) {
+    if actions.pressed(Action::MoveLeft) {
         move_vec.x -= 1.0;
     }
-    if keys.pressed(KeyCode::D) {
+    if actions.pressed(Action::MoveRight) {
         move_vec.x += 1.0;
     }
 
-    if keys.pressed(KeyCode::E) {
+    if actions.pressed(Action::MoveUp) {
         move_vec.y += 1.0;
     }
-    if keys.pressed(KeyCode::Q) {
+    if actions.pressed(Action::MoveDown) {
         move_vec.y -= 1.0;
     }
 
-    let mouse_sensitivity = 0.5;
     let mut mouse_delta = Vec2::ZERO;
-    if mouse_buttons.pressed(MouseButton::Right) {
+    if actions.pressed(Action::Look) {
         for event in mouse_motion_events.iter() {
             mouse_delta += event.delta;
         }
     }
 
-    let move_vec = camera_rig.final_transform.rotation * move_vec * 10.0f32.powf(boost);
-
-    camera_rig
-        .driver_mut::<Position>()
-        .translate(move_vec * time_delta_seconds * 2.5);
-
-    camera_rig.driver_mut::<YawPitch>().rotate_yaw_pitch(
-        -0.1 * mouse_delta.x * mouse_sensitivity,
-        -0.1 * mouse_delta.y * mouse_sensitivity,
-    );
-
-    camera_rig.update(time_delta_seconds);
-
-    let mut camera_transform = query.iter_mut().next().unwrap();
-    camera_transform.translation = camera_rig.final_transform.position;
-    camera_transform.rotation = camera_rig.final_transform.rotation;
-}
-
-fn change_camera_mode(
-    time: Res<Time>,
-    keys: Res<Input<KeyCode>>,
-    mut camera_rig: ResMut<CameraRig>,
-    mut query: Query<&mut Transform, With<DefaultCamera>>,
-) {
-    let time_delta_seconds: f32 = time.delta_seconds();
-
-    // MOBA view
-    if keys.pressed(KeyCode::M) {
-        camera_rig
-            .driver_mut::<YawPitch>()
-            .set_rotation_quat(Quat::IDENTITY);
-
-        camera_rig
-            .driver_mut::<YawPitch>()
-            .rotate_yaw_pitch(ISOMETRIC_VIEW_YAW, ISOMETRIC_VIEW_PITCH);
-
-        camera_rig.driver_mut::<Position>().position.y = ISOMETRIC_VIEWING_HEIGHT;
+    let mut stick_look = Vec2::ZERO;
+    if *stick_mode == StickMode::Camera {
+        let left_stick = actions.stick(Stick::Left);
+        move_vec.x += left_stick.x;
+        move_vec.z -= left_stick.y;
+        stick_look = actions.stick(Stick::Right) * settings.gamepad_look_speed * time_delta_seconds;
+    }
 
-        camera_rig.update(time_delta_seconds);
+    let move_vec =
+        camera_rig.final_transform.rotation * move_vec * settings.boost_factor.powf(boost);
 
-        let mut camera_transform = query.iter_mut().next().unwrap();
-        camera_transform.rotation = camera_rig.final_transform.rotation;
-        camera_transform.translation = camera_rig.final_transform.position;
+    if walking {
+        // the player walks around and carries the camera at its eyes
+        if let Some((player, controller)) = players.iter().next() {
+            camera_rig.driver_mut::<Position>().position =
+                player.translation + Vec3::Y * controller.eye_height();
+        }
+    } else if flying {
+        camera::move_camera(
+            &mut camera_rig,
+            move_vec * time_delta_seconds * settings.move_speed,
+            &collision,
+            &terrain,
+        );
     }
 
-    // FPS view
-    if keys.pressed(KeyCode::F) {
+    if walking || flying {
+        let mouse_look = settings.look_delta(mouse_delta) * settings.mouse_sensitivity;
+        let stick_look = settings.look_delta(stick_look);
         camera_rig
             .driver_mut::<YawPitch>()
-            .set_rotation_quat(Quat::IDENTITY);
-
-        camera_rig
-            .driver_mut::<YawPitch>()
-            .rotate_yaw_pitch(FPS_VIEW_YAW, FPS_VIEW_PITCH);
-
-        camera_rig.driver_mut::<Position>().position.y = FPS_VIEWING_HEIGHT;
+            .rotate_yaw_pitch(-mouse_look.x - stick_look.x, -mouse_look.y + stick_look.y);
+    }
 
-        camera_rig.update(time_delta_seconds);
+    camera_rig.update(time_delta_seconds);
 
-        let mut camera_transform = query.iter_mut().next().unwrap();
-        camera_transform.rotation = camera_rig.final_transform.rotation;
-        camera_transform.translation = camera_rig.final_transform.position;
-    }
+    let mut camera_transform = query.iter_mut().next().unwrap();
+    camera_transform.translation = camera_rig.final_transform.position;
+    camera_transform.rotation = camera_rig.final_transform.rotation;
 }
 
 fn pan_camera(
     time: Res<Time>,
-    keys: Res<Input<KeyCode>>,
+    actions: Res<ActionMap>,
+    settings: Res<CameraSettings>,
+    camera_mode: Res<CameraMode>,
+    collision: Res<CameraCollision>,
+    terrain: Res<Terrain>,
     mut camera_rig: ResMut<CameraRig>,
     mut query: Query<&mut Transform, With<DefaultCamera>>,
 ) {
+    if camera_mode.follows_player(&collision) {
+        return;
+    }
     let time_delta_seconds: f32 = time.delta_seconds();
     let mut move_vec = Vec3::ZERO;
-    if keys.pressed(KeyCode::Up) {
+    if actions.pressed(Action::PanForward) {
         move_vec.z -= 1.0;
     }
-    if keys.pressed(KeyCode::Down) {
+    if actions.pressed(Action::PanBackward) {
         move_vec.z += 1.0;
     }
-    if keys.pressed(KeyCode::Left) {
+    if actions.pressed(Action::PanLeft) {
         move_vec.x -= 1.0;
     }
-    if keys.pressed(KeyCode::Right) {
+    if actions.pressed(Action::PanRight) {
         move_vec.x += 1.0;
     }
-    let translation = move_vec * 10.0 * time_delta_seconds * 2.5;
-    camera_rig.driver_mut::<Position>().translate(translation);
+    // pan along the ground relative to where the camera is turned
+    let yaw = camera_rig.driver_mut::<YawPitch>().yaw_degrees;
+    let move_vec = Quat::from_rotation_y(yaw.to_radians()) * move_vec;
+    let translation = move_vec * settings.pan_speed * time_delta_seconds;
+    camera::move_camera(&mut camera_rig, translation, &collision, &terrain);
 
     camera_rig.update(time_delta_seconds);
 
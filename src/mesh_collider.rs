@@ -0,0 +1,167 @@
+use crate::broad_phase::{Bounded, BroadPhaseUpdate};
+use bevy::asset::HandleId;
+use bevy::prelude::*;
+use bevy::render::mesh::{Indices, VertexAttributeValues};
+use bevy::render::render_resource::PrimitiveTopology;
+use parry3d::{
+    bounding_volume::AABB,
+    math::{Point, Real},
+    query::{Ray, RayCast, RayIntersection},
+    shape::SharedShape,
+};
+use std::collections::{HashMap, HashSet};
+
+/// which shape is built out of the mesh
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColliderShape {
+    /// the triangles of the mesh as they are
+    TriMesh,
+    /// the smallest convex shape around the vertices of the mesh, cheaper to cast rays against
+    /// and solid all the way through
+    ConvexHull,
+}
+
+impl Default for ColliderShape {
+    fn default() -> Self {
+        ColliderShape::TriMesh
+    }
+}
+
+/// a shape built from the mesh of the entity, so ray casts hit exactly what is drawn,
+/// it is built once the mesh is loaded and rebuilt when the mesh changes
+#[derive(Component, Clone, Default)]
+pub struct MeshCollider {
+    pub shape: ColliderShape,
+    /// the mesh and the kind of shape the collider was built from
+    built: Option<(HandleId, ColliderShape)>,
+    collider: Option<SharedShape>,
+}
+
+impl MeshCollider {
+    pub fn trimesh() -> Self {
+        MeshCollider {
+            shape: ColliderShape::TriMesh,
+            ..Default::default()
+        }
+    }
+
+    pub fn convex_hull() -> Self {
+        MeshCollider {
+            shape: ColliderShape::ConvexHull,
+            ..Default::default()
+        }
+    }
+}
+
+impl RayCast for MeshCollider {
+    fn cast_local_ray_and_get_normal(
+        &self,
+        ray: &Ray,
+        max_toi: Real,
+        solid: bool,
+    ) -> Option<RayIntersection> {
+        self.collider
+            .as_ref()?
+            .cast_local_ray_and_get_normal(ray, max_toi, solid)
+    }
+}
+
+impl Bounded for MeshCollider {
+    fn local_aabb(&self) -> AABB {
+        match &self.collider {
+            Some(collider) => collider.compute_local_aabb(),
+            None => AABB::new(Point::origin(), Point::origin()),
+        }
+    }
+}
+
+/// the shapes built from the meshes, shared by all of the entities using the same mesh,
+/// `None` for the meshes which have no triangles to build a shape out of
+#[derive(Default)]
+pub struct MeshColliders {
+    shapes: HashMap<(HandleId, ColliderShape), Option<SharedShape>>,
+}
+
+/// builds the `MeshCollider`s before the broad phase places them
+pub struct MeshColliderPlugin;
+
+impl Plugin for MeshColliderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MeshColliders>()
+            .add_system(build_mesh_colliders.before(BroadPhaseUpdate));
+    }
+}
+
+/// the shape out of the triangle list of the mesh
+fn build_shape(mesh: &Mesh, shape: ColliderShape) -> Option<SharedShape> {
+    if mesh.primitive_topology() != PrimitiveTopology::TriangleList {
+        return None;
+    }
+    let vertices: Vec<Point<Real>> = match mesh.attribute(Mesh::ATTRIBUTE_POSITION)? {
+        VertexAttributeValues::Float32x3(positions) => positions
+            .iter()
+            .map(|position| Point::new(position[0], position[1], position[2]))
+            .collect(),
+        _ => return None,
+    };
+    match shape {
+        ColliderShape::ConvexHull => SharedShape::convex_hull(&vertices),
+        ColliderShape::TriMesh => {
+            // a mesh without indices lists the vertices of each triangle in order
+            let indices: Vec<u32> = match mesh.indices() {
+                Some(Indices::U16(indices)) => indices.iter().map(|index| *index as u32).collect(),
+                Some(Indices::U32(indices)) => indices.clone(),
+                None => (0..vertices.len() as u32).collect(),
+            };
+            let triangles: Vec<[u32; 3]> = indices
+                .chunks_exact(3)
+                .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+                .collect();
+            if triangles.is_empty() {
+                return None;
+            }
+            Some(SharedShape::trimesh(vertices, triangles))
+        }
+    }
+}
+
+/// give the colliders the shape of their mesh, the meshes which are changed or removed
+/// are built again the next time they are used
+fn build_mesh_colliders(
+    meshes: Res<Assets<Mesh>>,
+    mut mesh_events: EventReader<AssetEvent<Mesh>>,
+    mut cache: ResMut<MeshColliders>,
+    mut colliders: Query<(&Handle<Mesh>, &mut MeshCollider)>,
+) {
+    let mut changed = HashSet::new();
+    for event in mesh_events.iter() {
+        match event {
+            AssetEvent::Created { .. } => (),
+            AssetEvent::Modified { handle } | AssetEvent::Removed { handle } => {
+                changed.insert(handle.id);
+            }
+        }
+    }
+    cache.shapes.retain(|(mesh, _), _| !changed.contains(mesh));
+
+    for (mesh, mut collider) in colliders.iter_mut() {
+        let key = (mesh.id, collider.shape);
+        if collider.built == Some(key) && !changed.contains(&mesh.id) {
+            continue;
+        }
+        let shape = match cache.shapes.get(&key) {
+            Some(shape) => shape.clone(),
+            None => match meshes.get(mesh) {
+                Some(mesh) => {
+                    let shape = build_shape(mesh, key.1);
+                    cache.shapes.insert(key, shape.clone());
+                    shape
+                }
+                // try again once the mesh is loaded
+                None => continue,
+            },
+        };
+        collider.built = Some(key);
+        collider.collider = shape;
+    }
+}
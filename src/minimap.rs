@@ -0,0 +1,334 @@
+use crate::camera::{self, CameraMode, MobaCamera};
+use crate::camera_settings::CameraSettings;
+use crate::mining::MiningTool;
+use crate::picking::{PickEvent, PickHit, PickLayer, Picking};
+use crate::player::{Player, PLAYER_HEIGHT};
+use crate::projectile::Projectile;
+use crate::terrain::{Terrain, VOXEL_SIZE};
+use crate::DefaultCamera;
+use bevy::prelude::*;
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use dolly::prelude::CameraRig;
+use std::collections::HashSet;
+
+/// the minimap shows this many voxels around the player in each direction
+const MINIMAP_RADIUS: i32 = 48;
+/// size of the minimap on the screen, in pixels
+const MINIMAP_SIZE: f32 = 192.0;
+/// pixels in between the minimap and the top right corner of the window
+const MINIMAP_MARGIN: f32 = 10.0;
+/// seconds in between redrawing the terrain of the minimap
+const MINIMAP_REFRESH_INTERVAL: f32 = 0.25;
+/// how many blocks below the head of the player the minimap looks for the floor
+const MINIMAP_DEPTH: i32 = 16;
+/// the icons are kept around and hidden when they are not used
+const MINIMAP_ICONS: usize = 64;
+/// size of the icons on the minimap, in pixels
+const ICON_SIZE: f32 = 6.0;
+/// ores next to the air within this many voxels of the player are discovered
+const DISCOVERY_RADIUS: i32 = 8;
+
+/// the minimap in the top right corner of the window, a top down view of the terrain around
+/// the player drawn into a texture, with icons for the player, the IK arm, the projectiles
+/// and the ores found so far
+pub struct Minimap {
+    /// the UI node showing the minimap, clicking it is picked on the UI layer
+    entity: Entity,
+    image: Handle<Image>,
+    icons: Vec<Entity>,
+    /// the voxel column in the middle of the texture as it was last drawn
+    center: IVec3,
+    /// the ores that have been seen next to the air, kept until they are mined
+    discovered: HashSet<IVec3>,
+    timer: Timer,
+}
+
+impl Minimap {
+    /// where this world location is on the minimap, in pixels from its top left corner
+    fn to_map(&self, location: Vec3) -> Vec2 {
+        let scale = MINIMAP_SIZE / (MINIMAP_RADIUS * 2) as f32;
+        let corner = self.center - IVec3::new(MINIMAP_RADIUS, 0, MINIMAP_RADIUS);
+        Vec2::new(location.x - corner.x as f32, location.z - corner.z as f32) * scale
+    }
+
+    /// the world location on the ground at this many pixels from the top left corner of the minimap
+    fn to_world(&self, map: Vec2) -> Vec3 {
+        let scale = MINIMAP_SIZE / (MINIMAP_RADIUS * 2) as f32;
+        let corner = self.center - IVec3::new(MINIMAP_RADIUS, 0, MINIMAP_RADIUS);
+        Vec3::new(
+            corner.x as f32 + map.x / scale,
+            0.0,
+            corner.z as f32 + map.y / scale,
+        )
+    }
+}
+
+#[derive(Component)]
+pub struct MinimapIcon;
+
+pub(crate) fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+    let size = (MINIMAP_RADIUS * 2) as u32;
+    let image = images.add(Image::new_fill(
+        Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        &[0, 0, 0, 255],
+        TextureFormat::Rgba8UnormSrgb,
+    ));
+
+    // the minimap is drawn on the CPU instead of by a second camera,
+    // rendering a camera into a texture is not supported by bevy yet
+    commands.spawn_bundle(UiCameraBundle::default());
+    let mut icons = vec![];
+    let entity = commands
+        .spawn_bundle(ImageBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    top: Val::Px(MINIMAP_MARGIN),
+                    right: Val::Px(MINIMAP_MARGIN),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Px(MINIMAP_SIZE), Val::Px(MINIMAP_SIZE)),
+                ..Default::default()
+            },
+            image: image.clone().into(),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            for _ in 0..MINIMAP_ICONS {
+                let icon = parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            display: Display::None,
+                            position_type: PositionType::Absolute,
+                            size: Size::new(Val::Px(ICON_SIZE), Val::Px(ICON_SIZE)),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .insert(MinimapIcon)
+                    .id();
+                icons.push(icon);
+            }
+        })
+        .id();
+
+    commands.insert_resource(Minimap {
+        entity,
+        image,
+        icons,
+        center: IVec3::ZERO,
+        discovered: HashSet::new(),
+        timer: Timer::from_seconds(MINIMAP_REFRESH_INTERVAL, true),
+    });
+}
+
+/// the color of the top most block at or below the head of the player, darker the deeper it is,
+/// the walls which are as high as the head are darker still
+fn column_color(terrain: &Terrain, x: i32, z: i32, head: i32) -> [u8; 4] {
+    for depth in 0..MINIMAP_DEPTH {
+        let block = terrain.block_at(IVec3::new(x, head - depth, z));
+        if block.is_solid() {
+            let shade = if depth == 0 {
+                0.5
+            } else {
+                1.0 - 0.6 * (depth - 1) as f32 / MINIMAP_DEPTH as f32
+            };
+            let color = block.color();
+            return [
+                (color.r() * shade * 255.0) as u8,
+                (color.g() * shade * 255.0) as u8,
+                (color.b() * shade * 255.0) as u8,
+                255,
+            ];
+        }
+    }
+    [0, 0, 0, 255]
+}
+
+/// whether any of the faces of this voxel is open to the air
+fn is_exposed(terrain: &Terrain, voxel: IVec3) -> bool {
+    [IVec3::X, IVec3::Y, IVec3::Z].into_iter().any(|axis| {
+        !terrain.block_at(voxel + axis).is_solid() || !terrain.block_at(voxel - axis).is_solid()
+    })
+}
+
+/// redraw the terrain around the player into the minimap texture and look for the ores
+/// next to the air around the player
+pub(crate) fn refresh_minimap(
+    time: Res<Time>,
+    terrain: Res<Terrain>,
+    camera_rig: Res<CameraRig>,
+    mut minimap: ResMut<Minimap>,
+    mut images: ResMut<Assets<Image>>,
+    players: Query<&Transform, With<Player>>,
+) {
+    if !minimap.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    let (feet, head) = match players.iter().next() {
+        Some(player) => (
+            player.translation,
+            player.translation + Vec3::Y * PLAYER_HEIGHT * 0.9,
+        ),
+        None => (
+            camera_rig.final_transform.position,
+            camera_rig.final_transform.position,
+        ),
+    };
+    let center = Terrain::world_to_voxel(feet);
+    let head = Terrain::world_to_voxel(head).y;
+    minimap.center = center;
+
+    if let Some(image) = images.get_mut(&minimap.image) {
+        let size = MINIMAP_RADIUS * 2;
+        for row in 0..size {
+            for column in 0..size {
+                let x = center.x - MINIMAP_RADIUS + column;
+                let z = center.z - MINIMAP_RADIUS + row;
+                let start = ((row * size + column) * 4) as usize;
+                image.data[start..start + 4].copy_from_slice(&column_color(&terrain, x, z, head));
+            }
+        }
+    }
+
+    minimap
+        .discovered
+        .retain(|voxel| terrain.block_at(*voxel).is_ore());
+    for y in -DISCOVERY_RADIUS..=DISCOVERY_RADIUS {
+        for z in -DISCOVERY_RADIUS..=DISCOVERY_RADIUS {
+            for x in -DISCOVERY_RADIUS..=DISCOVERY_RADIUS {
+                let voxel = center + IVec3::new(x, y, z);
+                if terrain.block_at(voxel).is_ore() && is_exposed(&terrain, voxel) {
+                    minimap.discovered.insert(voxel);
+                }
+            }
+        }
+    }
+}
+
+/// place the icons of the player, the IK arm, the flying projectiles and the discovered ores
+/// on the minimap, the icons outside of it are hidden
+pub(crate) fn update_minimap_icons(
+    terrain: Res<Terrain>,
+    minimap: Res<Minimap>,
+    players: Query<&GlobalTransform, With<Player>>,
+    tools: Query<&GlobalTransform, With<MiningTool>>,
+    projectiles: Query<(&GlobalTransform, &Projectile)>,
+    mut icons: Query<(&mut Style, &mut UiColor), With<MinimapIcon>>,
+) {
+    // the icons added later are drawn on top
+    let mut markers: Vec<(Vec3, Color)> = minimap
+        .discovered
+        .iter()
+        .map(|voxel| {
+            let center = Terrain::voxel_to_world(*voxel) + Vec3::splat(VOXEL_SIZE / 2.0);
+            (center, terrain.block_at(*voxel).color())
+        })
+        .collect();
+    markers.extend(
+        projectiles
+            .iter()
+            .filter(|(_, projectile)| projectile.is_active())
+            .map(|(transform, _)| (transform.translation, Color::ORANGE)),
+    );
+    markers.extend(
+        tools
+            .iter()
+            .map(|transform| (transform.translation, Color::RED)),
+    );
+    markers.extend(
+        players
+            .iter()
+            .map(|transform| (transform.translation, Color::CYAN)),
+    );
+
+    let mut shown = markers.into_iter().rev().filter_map(|(location, color)| {
+        let map = minimap.to_map(location);
+        let inside = map.min_element() >= 0.0 && map.max_element() < MINIMAP_SIZE;
+        inside.then(|| (map, color))
+    });
+    for icon in minimap.icons.iter().rev() {
+        if let Ok((mut style, mut ui_color)) = icons.get_mut(*icon) {
+            match shown.next() {
+                Some((map, color)) => {
+                    style.display = Display::Flex;
+                    style.position = Rect {
+                        left: Val::Px(map.x - ICON_SIZE / 2.0),
+                        top: Val::Px(map.y - ICON_SIZE / 2.0),
+                        ..Default::default()
+                    };
+                    ui_color.0 = color;
+                }
+                None => style.display = Display::None,
+            }
+        }
+    }
+}
+
+/// the minimap is picked in front of everything else when the cursor is over it,
+/// the point of the hit is where the cursor is on the ground
+pub(crate) fn pick_minimap(
+    windows: Res<Windows>,
+    minimap: Res<Minimap>,
+    mut picking: ResMut<Picking>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let cursor = match picking.cursor() {
+        Some(cursor) => cursor,
+        None => return,
+    };
+    // the cursor position starts from the bottom left of the window
+    let map = Vec2::new(
+        cursor.x - (window.width() - MINIMAP_MARGIN - MINIMAP_SIZE),
+        window.height() - MINIMAP_MARGIN - cursor.y,
+    );
+    if map.min_element() < 0.0 || map.max_element() >= MINIMAP_SIZE {
+        return;
+    }
+    let hit = PickHit {
+        entity: minimap.entity,
+        layer: PickLayer::Ui,
+        point: minimap.to_world(map),
+        normal: Vec3::Y,
+    };
+    picking.add_hit(0.0, hit);
+}
+
+/// move the MOBA camera to where the minimap is clicked
+pub(crate) fn click_minimap(
+    mut pick_events: EventReader<PickEvent>,
+    settings: Res<CameraSettings>,
+    minimap: Res<Minimap>,
+    moba_camera: Res<MobaCamera>,
+    mut camera_mode: ResMut<CameraMode>,
+    mut camera_rig: ResMut<CameraRig>,
+    projections: Query<&PerspectiveProjection, With<DefaultCamera>>,
+) {
+    for event in pick_events.iter() {
+        if let PickEvent::Pressed(hit) = event {
+            if hit.entity != minimap.entity {
+                continue;
+            }
+            let fov = match projections.iter().next() {
+                Some(projection) => projection.fov,
+                None => settings.fov(camera_mode.view).to_radians(),
+            };
+            camera::look_at_ground(
+                hit.point,
+                fov,
+                &settings,
+                &mut camera_mode,
+                &moba_camera,
+                &mut camera_rig,
+            );
+        }
+    }
+}
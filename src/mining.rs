@@ -0,0 +1,231 @@
+use crate::player::{Player, PLAYER_HEIGHT};
+use crate::terrain::{Block, BlockMaterials, Terrain, VOXEL_SIZE};
+use bevy::prelude::*;
+use std::collections::HashMap;
+
+/// length of the drill, its tip points to the -Z of the hand of the IK arm
+const TOOL_LENGTH: f32 = 0.2;
+/// how many different looks of cracks a block goes through before it breaks
+const CRACK_STAGES: usize = 4;
+/// size of the dropped item cube
+const DROPPED_ITEM_SIZE: f32 = 0.25;
+/// gravity pulling down the dropped items
+const GRAVITY: f32 = 9.8;
+/// the player picks up the dropped items this close to the middle of its body
+const PICKUP_RADIUS: f32 = 1.5;
+
+/// a tool that digs the voxel its tip is held against
+#[derive(Component)]
+pub struct MiningTool {
+    /// the voxel currently being mined
+    target: Option<IVec3>,
+    /// seconds the tip has been held against the target
+    damage: f32,
+    /// the entity showing the cracks on the target
+    crack: Option<Entity>,
+}
+
+impl Default for MiningTool {
+    fn default() -> Self {
+        MiningTool {
+            target: None,
+            damage: 0.0,
+            crack: None,
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct CrackOverlay;
+
+/// the resource that drops out of a broken block
+#[derive(Component)]
+pub struct DroppedItem {
+    pub block: Block,
+    velocity: Vec3,
+}
+
+/// how many of each block the player has picked up
+#[derive(Default)]
+pub struct Inventory(HashMap<Block, u32>);
+
+pub struct MiningAssets {
+    crack_mesh: Handle<Mesh>,
+    crack_materials: Vec<Handle<StandardMaterial>>,
+    item_mesh: Handle<Mesh>,
+}
+
+pub(crate) fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let crack_materials = (0..CRACK_STAGES)
+        .map(|stage| {
+            materials.add(StandardMaterial {
+                base_color: Color::rgba(0.0, 0.0, 0.0, 0.2 * (stage + 1) as f32),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                ..Default::default()
+            })
+        })
+        .collect();
+
+    commands.insert_resource(Inventory::default());
+    commands.insert_resource(MiningAssets {
+        // slightly bigger so it is not hidden by the faces of the block
+        crack_mesh: meshes.add(Mesh::from(shape::Cube {
+            size: VOXEL_SIZE * 1.02,
+        })),
+        crack_materials,
+        item_mesh: meshes.add(Mesh::from(shape::Cube {
+            size: DROPPED_ITEM_SIZE,
+        })),
+    });
+}
+
+/// attach the drill to the end effector of the IK arm
+pub(crate) fn spawn_tool(
+    parent: &mut ChildBuilder,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+) {
+    parent.spawn_bundle(PbrBundle {
+        mesh: meshes.add(Mesh::from(shape::Box::new(0.05, 0.05, TOOL_LENGTH))),
+        material: materials.add(StandardMaterial {
+            base_color: Color::GRAY,
+            metallic: 1.0,
+            perceptual_roughness: 0.3,
+            ..Default::default()
+        }),
+        transform: Transform::from_translation(-Vec3::Z * TOOL_LENGTH / 2.0),
+        ..Default::default()
+    });
+}
+
+/// damage the voxel touched by the tip of the mining tools, break it once
+/// it has been held long enough
+pub(crate) fn mine_terrain(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut terrain: ResMut<Terrain>,
+    mining_assets: Res<MiningAssets>,
+    block_materials: Res<BlockMaterials>,
+    mut tools: Query<(&GlobalTransform, &mut MiningTool)>,
+    mut cracks: Query<
+        (
+            &mut Transform,
+            &mut Handle<StandardMaterial>,
+            &mut Visibility,
+        ),
+        With<CrackOverlay>,
+    >,
+) {
+    for (transform, mut tool) in tools.iter_mut() {
+        let voxel = Terrain::world_to_voxel(transform.mul_vec3(-Vec3::Z * TOOL_LENGTH));
+        let block = terrain.block_at(voxel);
+
+        let progress = match block.hardness() {
+            Some(hardness) => {
+                if tool.target != Some(voxel) {
+                    tool.target = Some(voxel);
+                    tool.damage = 0.0;
+                }
+                tool.damage += time.delta_seconds();
+                tool.damage / hardness
+            }
+            None => {
+                tool.target = None;
+                tool.damage = 0.0;
+                0.0
+            }
+        };
+
+        let center = Terrain::voxel_to_world(voxel) + Vec3::splat(VOXEL_SIZE / 2.0);
+        if progress >= 1.0 {
+            terrain.set_block(voxel, Block::Air);
+            commands
+                .spawn_bundle(PbrBundle {
+                    mesh: mining_assets.item_mesh.clone(),
+                    material: block_materials.get(block),
+                    transform: Transform::from_translation(center),
+                    ..Default::default()
+                })
+                .insert(DroppedItem {
+                    block,
+                    velocity: Vec3::Y * 2.0,
+                });
+            tool.target = None;
+            tool.damage = 0.0;
+        }
+
+        let crack = match tool.crack {
+            Some(crack) => crack,
+            None => {
+                let crack = commands
+                    .spawn_bundle(PbrBundle {
+                        mesh: mining_assets.crack_mesh.clone(),
+                        material: mining_assets.crack_materials[0].clone(),
+                        visibility: Visibility { is_visible: false },
+                        ..Default::default()
+                    })
+                    .insert(CrackOverlay)
+                    .id();
+                tool.crack = Some(crack);
+                crack
+            }
+        };
+        if let Ok((mut crack_transform, mut material, mut visibility)) = cracks.get_mut(crack) {
+            visibility.is_visible = tool.target.is_some() && progress > 0.0;
+            if visibility.is_visible {
+                let stage = ((progress * CRACK_STAGES as f32) as usize).min(CRACK_STAGES - 1);
+                crack_transform.translation = center;
+                *material = mining_assets.crack_materials[stage].clone();
+            }
+        }
+    }
+}
+
+/// let the dropped items fall until they rest on top of a solid block
+pub(crate) fn drop_items(
+    time: Res<Time>,
+    terrain: Res<Terrain>,
+    mut items: Query<(&mut Transform, &mut DroppedItem)>,
+) {
+    let time_delta_seconds = time.delta_seconds();
+    for (mut transform, mut item) in items.iter_mut() {
+        transform.rotate(Quat::from_rotation_y(time_delta_seconds));
+
+        item.velocity.y -= GRAVITY * time_delta_seconds;
+        let next = transform.translation + item.velocity * time_delta_seconds;
+        let bottom = next - Vec3::Y * DROPPED_ITEM_SIZE / 2.0;
+        let below = Terrain::world_to_voxel(bottom);
+        if terrain.block_at(below).is_solid() && item.velocity.y < 0.0 {
+            let ground = Terrain::voxel_to_world(below + IVec3::Y).y;
+            transform.translation.y = ground + DROPPED_ITEM_SIZE / 2.0;
+            item.velocity = Vec3::ZERO;
+        } else {
+            transform.translation = next;
+        }
+    }
+}
+
+/// the player picks up the dropped items it walks over
+pub(crate) fn pickup_items(
+    mut commands: Commands,
+    mut inventory: ResMut<Inventory>,
+    players: Query<&Transform, With<Player>>,
+    items: Query<(Entity, &Transform, &DroppedItem)>,
+) {
+    for player in players.iter() {
+        let body = player.translation + Vec3::Y * PLAYER_HEIGHT / 2.0;
+        for (entity, transform, item) in items.iter() {
+            if transform.translation.distance(body) <= PICKUP_RADIUS {
+                commands.entity(entity).despawn();
+                let count = inventory.0.entry(item.block).or_insert(0);
+                *count += 1;
+                info!("picked up {:?}, {} in total", item.block, count);
+            }
+        }
+    }
+}
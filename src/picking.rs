@@ -0,0 +1,306 @@
+use crate::actions::{Action, ActionMap};
+use crate::broad_phase::{BroadPhase, BroadPhaseUpdate};
+use crate::selector;
+use crate::DefaultCamera;
+use bevy::prelude::*;
+use parry3d::query::{Ray, RayCast};
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+
+/// the cursor has to move this many pixels while pressing an entity before it starts dragging it
+const DRAG_THRESHOLD: f32 = 4.0;
+
+/// the layers the entities are picked on, the cursor picks the closest entity on the first
+/// of these layers it hits anything on, no matter how close the entities on the later layers are
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum PickLayer {
+    /// the HUD and the minimap, which are drawn over everything else
+    Ui,
+    /// the handles to move things around with, like the IK cubes
+    Gizmo,
+    World,
+    Terrain,
+}
+
+impl Default for PickLayer {
+    fn default() -> Self {
+        PickLayer::World
+    }
+}
+
+/// which layer the entity is picked on and whether it is picked at all,
+/// the pickable entities without this are picked on the world layer
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Pickable {
+    pub layer: PickLayer,
+    pub enabled: bool,
+}
+
+impl Pickable {
+    pub fn new(layer: PickLayer) -> Self {
+        Pickable {
+            layer,
+            enabled: true,
+        }
+    }
+}
+
+impl Default for Pickable {
+    fn default() -> Self {
+        Pickable::new(PickLayer::default())
+    }
+}
+
+/// where the cursor ray hit an entity
+#[derive(Clone, Copy, Debug)]
+pub struct PickHit {
+    pub entity: Entity,
+    pub layer: PickLayer,
+    /// the world location where the ray hit
+    pub point: Vec3,
+    /// the surface normal at the hit point
+    pub normal: Vec3,
+}
+
+/// sent when the cursor interacts with the entities which are pickable,
+/// pressing and dragging is done with the select action
+#[derive(Clone, Copy, Debug)]
+pub enum PickEvent {
+    /// the cursor moved onto the entity
+    HoverEnter(PickHit),
+    /// the cursor moved off the entity, with where it last hit it
+    HoverExit(PickHit),
+    /// the entity under the cursor is pressed
+    Pressed(PickHit),
+    /// the pressed entity is let go, wherever the cursor is by then
+    Released(PickHit),
+    /// the cursor moved far enough while pressing the entity to start dragging it
+    DragStart(PickHit),
+    /// the entity is dragged, the point is where the cursor is on the plane
+    /// facing the camera through where the drag started
+    Drag(PickHit),
+    /// the dragged entity is let go
+    DragEnd(PickHit),
+}
+
+#[derive(SystemLabel, Clone, Hash, Debug, PartialEq, Eq)]
+pub enum PickingSystem {
+    /// the ray from the camera through the cursor is updated
+    CursorRay,
+    /// the ray is cast against the pickable components in its way
+    Cast,
+    /// the closest hit on the first layer with any hits is turned into the pick events
+    Events,
+}
+
+/// the entity being pressed
+#[derive(Clone, Copy)]
+struct Press {
+    hit: PickHit,
+    /// where the cursor was when pressing it
+    cursor: Vec2,
+    /// the entity is dragged along the plane facing back along the ray that pressed it
+    facing: Vec3,
+}
+
+/// the state of the picking in between frames
+#[derive(Default)]
+pub struct Picking {
+    /// the ray from the camera through the cursor this frame
+    ray: Option<Ray>,
+    cursor: Option<Vec2>,
+    /// everything the ray hit this frame with its time of impact
+    hits: Vec<(f32, PickHit)>,
+    hovered: Option<PickHit>,
+    pressed: Option<Press>,
+    /// the last point the pressed entity was dragged to
+    dragged: Option<PickHit>,
+}
+
+impl Picking {
+    /// the ray from the camera through the cursor this frame
+    pub fn ray(&self) -> Option<Ray> {
+        self.ray
+    }
+
+    pub fn cursor(&self) -> Option<Vec2> {
+        self.cursor
+    }
+
+    /// add what the ray hit with its time of impact, for the things which are
+    /// not picked with a `PickablePlugin`, this is done in the `PickingSystem::Cast` systems
+    pub fn add_hit(&mut self, toi: f32, hit: PickHit) {
+        self.hits.push((toi, hit));
+    }
+
+    /// the closest hit under the cursor on the first layer with any hits
+    pub fn hovered(&self) -> Option<PickHit> {
+        self.hovered
+    }
+
+    /// whether the cursor is over something picked on this layer, the actions that don't
+    /// go through the pick events can check it so a click is not used twice
+    pub fn is_over(&self, layer: PickLayer) -> bool {
+        self.hovered.map_or(false, |hit| hit.layer == layer)
+    }
+}
+
+/// sends the pick events for the entities made pickable by the `PickablePlugin`
+pub struct PickingPlugin;
+
+impl Plugin for PickingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Picking>()
+            .add_event::<PickEvent>()
+            .add_system(update_cursor_ray.label(PickingSystem::CursorRay))
+            .add_system(
+                send_pick_events
+                    .label(PickingSystem::Events)
+                    .after(PickingSystem::Cast),
+            );
+    }
+}
+
+/// makes the entities with the component pickable, using its ray cast,
+/// the component needs its `BroadPhasePlugin` as well
+pub struct PickablePlugin<T>(PhantomData<fn() -> T>);
+
+impl<T> Default for PickablePlugin<T> {
+    fn default() -> Self {
+        PickablePlugin(PhantomData)
+    }
+}
+
+impl<T> Plugin for PickablePlugin<T>
+where
+    T: Component + RayCast,
+{
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            cast_pickable::<T>
+                .label(PickingSystem::Cast)
+                .after(PickingSystem::CursorRay)
+                .after(BroadPhaseUpdate),
+        );
+    }
+}
+
+fn update_cursor_ray(
+    windows: Res<Windows>,
+    mut picking: ResMut<Picking>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    default_camera: Query<Entity, With<DefaultCamera>>,
+) {
+    picking.hits.clear();
+    let camera = default_camera.iter().next();
+    picking.ray = camera.and_then(|camera| selector::cursor_ray(&windows, &cameras, camera));
+    picking.cursor = camera
+        .and_then(|camera| cameras.get(camera).ok())
+        .and_then(|(camera, _)| windows.get(camera.window))
+        .and_then(|window| window.cursor_position());
+}
+
+fn cast_pickable<T>(
+    mut picking: ResMut<Picking>,
+    broad_phase: Res<BroadPhase<T>>,
+    pickables: Query<(&GlobalTransform, &T, Option<&Pickable>)>,
+) where
+    T: Component + RayCast,
+{
+    let ray = match picking.ray {
+        Some(ray) => ray,
+        None => return,
+    };
+    for entity in broad_phase.candidates(&ray, f32::INFINITY) {
+        let (transform, component, pickable) = match pickables.get(entity) {
+            Ok(pickable) => pickable,
+            Err(_) => continue,
+        };
+        let pickable = pickable.copied().unwrap_or_default();
+        if !pickable.enabled {
+            continue;
+        }
+        if let Some(intersection) = selector::cast_ray(&ray, transform, component, f32::INFINITY) {
+            let hit = PickHit {
+                entity,
+                layer: pickable.layer,
+                point: ray.point_at(intersection.toi).into(),
+                normal: intersection.normal.into(),
+            };
+            picking.hits.push((intersection.toi, hit));
+        }
+    }
+}
+
+fn send_pick_events(
+    actions: Res<ActionMap>,
+    mut picking: ResMut<Picking>,
+    mut pick_events: EventWriter<PickEvent>,
+) {
+    let closest = picking
+        .hits
+        .iter()
+        .min_by(|a, b| {
+            a.1.layer
+                .cmp(&b.1.layer)
+                .then(a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))
+        })
+        .map(|(_, hit)| *hit);
+
+    let entered = closest.map(|hit| hit.entity) != picking.hovered.map(|hit| hit.entity);
+    if entered {
+        if let Some(hovered) = picking.hovered {
+            pick_events.send(PickEvent::HoverExit(hovered));
+        }
+        if let Some(hit) = closest {
+            pick_events.send(PickEvent::HoverEnter(hit));
+        }
+    }
+    picking.hovered = closest;
+
+    if actions.just_pressed(Action::Select) {
+        if let (Some(hit), Some(cursor), Some(ray)) = (closest, picking.cursor, picking.ray) {
+            pick_events.send(PickEvent::Pressed(hit));
+            let dir: Vec3 = ray.dir.into();
+            picking.pressed = Some(Press {
+                hit,
+                cursor,
+                facing: -dir,
+            });
+        }
+    }
+
+    if let Some(press) = picking.pressed {
+        if actions.pressed(Action::Select) {
+            let moved = picking.cursor.map_or(false, |cursor| {
+                cursor.distance(press.cursor) >= DRAG_THRESHOLD
+            });
+            if picking.dragged.is_none() && moved {
+                pick_events.send(PickEvent::DragStart(press.hit));
+                picking.dragged = Some(press.hit);
+            }
+            if let (Some(dragged), Some(ray)) = (picking.dragged, picking.ray) {
+                if let Some(point) = selector::intersect_plane(&ray, press.hit.point, press.facing)
+                {
+                    if point != dragged.point {
+                        let hit = PickHit { point, ..dragged };
+                        pick_events.send(PickEvent::Drag(hit));
+                        picking.dragged = Some(hit);
+                    }
+                }
+            }
+        }
+        // a release missed while the window was not focused also lets go
+        if !actions.pressed(Action::Select) {
+            let released = closest
+                .filter(|hit| hit.entity == press.hit.entity)
+                .unwrap_or(press.hit);
+            pick_events.send(PickEvent::Released(released));
+            if let Some(dragged) = picking.dragged {
+                pick_events.send(PickEvent::DragEnd(dragged));
+            }
+            picking.pressed = None;
+            picking.dragged = None;
+        }
+    }
+}
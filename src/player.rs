@@ -0,0 +1,237 @@
+use crate::actions::{Action, ActionMap, Stick, StickMode};
+use crate::camera::{CameraCollision, CameraMode};
+use crate::terrain::Terrain;
+use crate::FPS_VIEWING_HEIGHT;
+use bevy::prelude::*;
+use dolly::prelude::CameraRig;
+use parry3d::shape::Capsule;
+
+/// height of the player model, its transform is at the feet
+pub const PLAYER_HEIGHT: f32 = 1.8;
+const PLAYER_CROUCH_HEIGHT: f32 = 1.2;
+const PLAYER_RADIUS: f32 = 0.3;
+/// meters per second the player walks
+const PLAYER_SPEED: f32 = 4.0;
+const PLAYER_CROUCH_SPEED: f32 = 2.0;
+/// meters per second the player crouches down and stands up
+const PLAYER_CROUCH_RATE: f32 = 4.0;
+/// upward speed at the start of a jump, enough to get on top of a block
+const PLAYER_JUMP_SPEED: f32 = 7.0;
+const PLAYER_GRAVITY: f32 = 20.0;
+/// the fastest the player falls
+const PLAYER_MAX_FALL_SPEED: f32 = 50.0;
+/// how high the ledges are that the player walks up onto without jumping
+const PLAYER_STEP_HEIGHT: f32 = 1.05;
+/// the steepest ground in degrees the player stands on, it slides off anything steeper
+const PLAYER_MAX_SLOPE: f32 = 45.0;
+
+/// the character the camera follows in the FPS and third person views
+#[derive(Component)]
+pub struct Player;
+
+/// the visible model of the player, squashed down while crouching
+#[derive(Component)]
+pub struct PlayerModel;
+
+/// the state of the character controller walking the player around
+#[derive(Component)]
+pub struct PlayerController {
+    vertical_speed: f32,
+    grounded: bool,
+    /// the current height of the capsule, in between crouching and standing
+    height: f32,
+}
+
+impl PlayerController {
+    /// how high the eyes are above the feet
+    pub fn eye_height(&self) -> f32 {
+        FPS_VIEWING_HEIGHT - (PLAYER_HEIGHT - self.height)
+    }
+
+    fn capsule(&self) -> Capsule {
+        Capsule::new_y(self.height / 2.0 - PLAYER_RADIUS, PLAYER_RADIUS)
+    }
+}
+
+/// spawn the player on top of the terrain at the origin,
+/// this runs after the terrain is set up
+pub(crate) fn setup(
+    mut commands: Commands,
+    terrain: Res<Terrain>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let ground = terrain.surface_height(0, 0) + 1;
+    commands
+        .spawn_bundle((
+            Transform::from_xyz(0.0, ground as f32, 0.0),
+            GlobalTransform::default(),
+        ))
+        .insert(Player)
+        .insert(PlayerController {
+            vertical_speed: 0.0,
+            grounded: false,
+            height: PLAYER_HEIGHT,
+        })
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(PbrBundle {
+                    mesh: meshes.add(Mesh::from(shape::Capsule {
+                        radius: PLAYER_RADIUS,
+                        depth: PLAYER_HEIGHT - PLAYER_RADIUS * 2.0,
+                        ..Default::default()
+                    })),
+                    material: materials.add(StandardMaterial {
+                        base_color: Color::rgb(0.2, 0.4, 0.9),
+                        ..Default::default()
+                    }),
+                    transform: Transform::from_xyz(0.0, PLAYER_HEIGHT / 2.0, 0.0),
+                    ..Default::default()
+                })
+                .insert(PlayerModel);
+        });
+}
+
+fn is_walkable(normal: Vec3) -> bool {
+    normal.y >= PLAYER_MAX_SLOPE.to_radians().cos()
+}
+
+/// walk the capsule up onto the ledge in its way,
+/// returns where it ends up if it stands on top of the ledge
+fn step_up(terrain: &Terrain, capsule: &Capsule, center: Vec3, walk: Vec3) -> Option<Vec3> {
+    let up = terrain.sweep_shape(capsule, center, Vec3::Y * PLAYER_STEP_HEIGHT);
+    let across = terrain.sweep_shape(capsule, up.center, walk);
+    let down = terrain.sweep_shape(capsule, across.center, -Vec3::Y * (up.center.y - center.y));
+    if down.normals.iter().any(|normal| is_walkable(*normal)) {
+        Some(down.center)
+    } else {
+        None
+    }
+}
+
+/// walk the player relative to where the camera is looking while the camera follows it,
+/// the player always falls down to the ground
+pub(crate) fn move_player(
+    time: Res<Time>,
+    actions: Res<ActionMap>,
+    stick_mode: Res<StickMode>,
+    camera_mode: Res<CameraMode>,
+    collision: Res<CameraCollision>,
+    camera_rig: Res<CameraRig>,
+    terrain: Res<Terrain>,
+    mut players: Query<(&mut Transform, &mut PlayerController)>,
+    mut models: Query<&mut Transform, (With<PlayerModel>, Without<PlayerController>)>,
+) {
+    let time_delta_seconds = time.delta_seconds();
+    let walking = camera_mode.follows_player(&collision);
+
+    let mut move_vec = Vec3::ZERO;
+    if walking {
+        if actions.pressed(Action::MoveForward) {
+            move_vec.z -= 1.0;
+        }
+        if actions.pressed(Action::MoveBackward) {
+            move_vec.z += 1.0;
+        }
+        if actions.pressed(Action::MoveLeft) {
+            move_vec.x -= 1.0;
+        }
+        if actions.pressed(Action::MoveRight) {
+            move_vec.x += 1.0;
+        }
+        if *stick_mode == StickMode::Camera {
+            let left_stick = actions.stick(Stick::Left);
+            move_vec.x += left_stick.x;
+            move_vec.z -= left_stick.y;
+        }
+    }
+    let (yaw, _, _) = camera_rig.final_transform.rotation.to_euler(EulerRot::YXZ);
+    let move_vec = Quat::from_rotation_y(yaw) * move_vec;
+    let move_vec = if move_vec.length() > 1.0 {
+        move_vec.normalize()
+    } else {
+        move_vec
+    };
+    let jump = walking && actions.just_pressed(Action::Jump);
+    let crouch = walking && actions.pressed(Action::Crouch);
+
+    for (mut transform, mut controller) in players.iter_mut() {
+        // wait for the ground to be generated before letting the player fall onto it
+        if !terrain.is_generated(Terrain::world_to_voxel(transform.translation)) {
+            continue;
+        }
+
+        // crouch down right away, but only stand back up when there is room above
+        let target_height = if crouch {
+            PLAYER_CROUCH_HEIGHT
+        } else {
+            PLAYER_HEIGHT
+        };
+        let rate = PLAYER_CROUCH_RATE * time_delta_seconds;
+        if target_height < controller.height {
+            controller.height = (controller.height - rate).max(target_height);
+        } else if target_height > controller.height {
+            let grow = (target_height - controller.height).min(rate);
+            let center = transform.translation + Vec3::Y * controller.height / 2.0;
+            let sweep = terrain.sweep_shape(&controller.capsule(), center, Vec3::Y * grow);
+            if sweep.normals.is_empty() {
+                controller.height += grow;
+            }
+        }
+
+        let speed = if controller.height < PLAYER_HEIGHT {
+            PLAYER_CROUCH_SPEED
+        } else if actions.pressed(Action::Boost) {
+            PLAYER_SPEED * 2.0
+        } else {
+            PLAYER_SPEED
+        };
+
+        let capsule = controller.capsule();
+        let half_height = controller.height / 2.0;
+        let mut center = transform.translation + Vec3::Y * half_height;
+
+        let walk = move_vec * speed * time_delta_seconds;
+        if walk != Vec3::ZERO {
+            let start = center;
+            let sweep = terrain.sweep_shape(&capsule, start, walk);
+            center = sweep.center;
+            let blocked = sweep.normals.iter().any(|normal| !is_walkable(*normal));
+            if blocked && controller.grounded {
+                let horizontal = |p: Vec3| (p - start) * Vec3::new(1.0, 0.0, 1.0);
+                if let Some(stepped) = step_up(&terrain, &capsule, start, walk) {
+                    if horizontal(stepped).length() > horizontal(center).length() {
+                        center = stepped;
+                    }
+                }
+            }
+            transform.rotation = Quat::from_rotation_y(f32::atan2(-move_vec.x, -move_vec.z));
+        }
+
+        if controller.grounded && jump {
+            controller.vertical_speed = PLAYER_JUMP_SPEED;
+        }
+        controller.vertical_speed = (controller.vertical_speed
+            - PLAYER_GRAVITY * time_delta_seconds)
+            .max(-PLAYER_MAX_FALL_SPEED);
+        let fall = Vec3::Y * controller.vertical_speed * time_delta_seconds;
+        let sweep = terrain.sweep_shape(&capsule, center, fall);
+        center = sweep.center;
+        controller.grounded = false;
+        for normal in sweep.normals {
+            if controller.vertical_speed <= 0.0 && is_walkable(normal) {
+                controller.grounded = true;
+                controller.vertical_speed = 0.0;
+            } else if controller.vertical_speed > 0.0 && normal.y < 0.0 {
+                // bumped the head
+                controller.vertical_speed = 0.0;
+            }
+        }
+
+        transform.translation = center - Vec3::Y * half_height;
+        for mut model in models.iter_mut() {
+            model.translation.y = half_height;
+            model.scale.y = controller.height / PLAYER_HEIGHT;
+        }
+    }
+}
@@ -1,90 +1,463 @@
+use crate::actions::{Action, ActionMap};
+use crate::broad_phase::BroadPhase;
+use crate::ik::IkCubes;
+use crate::mesh_collider::MeshCollider;
+use crate::picking::{PickLayer, Picking};
 use crate::selector;
+use crate::terrain::Terrain;
+use crate::weapon::Weapons;
 use crate::DefaultCamera;
 use bevy::prelude::*;
-use dolly::prelude::CameraRig;
 use parry3d::{
     math::{Point, Vector},
-    query::Ray,
+    query::{Ray, RayIntersection},
 };
+use rand::Rng;
+use serde::Deserialize;
+use std::{cmp::Ordering, f32::consts::TAU};
 /// flag whether to make projectile start from the center of the screen
 ///
 /// false, the projectile come from the mouse position
 const PROJECTILE_FROM_CENTER: bool = false;
 
-/// flag whether to make the projectile seek the target, in this case the car
+/// flag whether to make the projectile seek the target, in this case the IK cube under the cursor
 const PROJECTILE_SEEK_TARGET: bool = true;
 /// speed of the projectile
 const PROJECTILE_SEEK_SPEED: f32 = 100.0;
 /// speed of the projectile at launch
-const PROJECTILE_LAUNCH_SPEED: f32 = 20.0;
+pub(crate) const PROJECTILE_LAUNCH_SPEED: f32 = 20.0;
+/// how fast a seeking projectile speeds up from the launch speed to the seek speed, in m/s²
+const PROJECTILE_SEEK_ACCELERATION: f32 = 40.0;
+/// how fast a seeking projectile can turn towards its target, in radians per second
+const PROJECTILE_SEEK_TURN_RATE: f32 = 3.0;
+/// radius of the sphere carved out of the terrain when a projectile hits
+const PROJECTILE_BLAST_RADIUS: f32 = 1.5;
+/// meters in front of the camera the projectiles are fired from
+const PROJECTILE_LAUNCH_OFFSET: f32 = 0.5;
+/// seconds a projectile flies before it is returned to the pool
+const PROJECTILE_MAX_LIFETIME: f64 = 5.0;
+/// meters a projectile flies away from where it was fired before it is returned to the pool
+const PROJECTILE_MAX_RANGE: f32 = 200.0;
+
+/// speed a ballistic projectile is thrown at
+pub(crate) const BALLISTIC_LAUNCH_SPEED: f32 = 15.0;
+/// downward acceleration of the ballistic projectiles, in m/s²
+const BALLISTIC_GRAVITY: f32 = 9.8;
+/// fraction of the velocity lost to air drag each second
+const BALLISTIC_DRAG: f32 = 0.1;
+/// fraction of the velocity kept when bouncing off the terrain
+const BALLISTIC_RESTITUTION: f32 = 0.4;
+/// a ballistic projectile bouncing slower than this comes to rest and explodes
+const BALLISTIC_REST_SPEED: f32 = 2.0;
+/// number of dots drawn along the predicted trajectory
+const TRAJECTORY_DOTS: usize = 40;
+/// seconds in between two dots of the predicted trajectory
+const TRAJECTORY_STEP: f32 = 0.05;
+
+/// how a projectile flies
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum ProjectileKind {
+    /// flies in a straight line, seeking the target if there is one
+    Straight,
+    /// thrown in an arc pulled by gravity and slowed down by drag,
+    /// bounces off the terrain until it comes to rest
+    Ballistic,
+}
 
 #[derive(Component)]
 pub struct Projectile {
-    /// the direction this projectile was fired
+    kind: ProjectileKind,
+    /// the normalized direction this projectile is flying to
     direction: Vec3,
+    /// the current speed of the projectile
+    speed: f32,
     /// time this projectile was fired
     fired: f64,
+    /// the location this projectile was fired from
+    origin: Vec3,
+    /// false when the projectile is waiting in the pool to be fired again
+    active: bool,
+}
+
+impl Projectile {
+    /// whether this projectile is flying instead of waiting in the pool
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+}
+
+/// the entity a seeking projectile is flying towards
+#[derive(Component)]
+pub struct Target(pub Entity);
+
+/// a dot drawn along the predicted trajectory of a ballistic projectile
+#[derive(Component)]
+pub struct TrajectoryDot;
+
+/// projectiles that have expired or hit something, hidden and kept around to be fired again
+/// instead of spawning a new entity each time
+#[derive(Default)]
+pub struct ProjectilePool(Vec<Entity>);
+
+impl ProjectilePool {
+    fn retire(&mut self, entity: Entity, projectile: &mut Projectile, visibility: &mut Visibility) {
+        projectile.active = false;
+        visibility.is_visible = false;
+        self.0.push(entity);
+    }
+}
+
+/// sent when a projectile hits the terrain or an object in the world
+pub struct ProjectileImpact {
+    /// the world location where the projectile hit
+    pub point: Vec3,
 }
 
-/// spawn a projectile at the mouse pointing direction
+pub(crate) fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(ProjectilePool::default());
+
+    let dot_mesh = meshes.add(Mesh::from(shape::Icosphere {
+        radius: 0.03,
+        subdivisions: 2,
+    }));
+    let dot_material = materials.add(StandardMaterial {
+        base_color: Color::YELLOW,
+        unlit: true,
+        ..Default::default()
+    });
+    for _ in 0..TRAJECTORY_DOTS {
+        commands
+            .spawn_bundle(PbrBundle {
+                mesh: dot_mesh.clone(),
+                material: dot_material.clone(),
+                visibility: Visibility { is_visible: false },
+                ..Default::default()
+            })
+            .insert(TrajectoryDot);
+    }
+}
+
+/// the direction the projectile is fired to, from the camera to the mouse or the center of the screen,
+/// the center is also used while the mouse is outside of the window
+fn launch_direction(camera_transform: &Transform, mouse_ray: Option<&Ray>) -> Vec3 {
+    let direction: Vec3 = match mouse_ray {
+        Some(mouse_ray) if !PROJECTILE_FROM_CENTER => mouse_ray.dir.into(),
+        _ => camera_transform.forward(),
+    };
+    direction.normalize()
+}
+
+/// where the projectile starts, right in front of the camera so the first sweep
+/// already hits the things close to it
+fn launch_location(camera_transform: &Transform, direction: Vec3) -> Vec3 {
+    camera_transform.translation + direction * PROJECTILE_LAUNCH_OFFSET
+}
+
+/// randomly deviate the direction within a cone of `spread` degrees
+fn apply_spread(direction: Vec3, spread: f32) -> Vec3 {
+    if spread <= 0.0 {
+        return direction;
+    }
+    let mut rng = rand::thread_rng();
+    let axis = Quat::from_axis_angle(direction, rng.gen_range(0.0..TAU))
+        * direction.any_orthonormal_vector();
+    Quat::from_axis_angle(axis, rng.gen_range(0.0..spread.to_radians())) * direction
+}
+
+/// apply gravity and air drag to the velocity of a ballistic projectile
+fn ballistic_velocity(velocity: Vec3, time_delta_seconds: f32) -> Vec3 {
+    let velocity = velocity - Vec3::Y * BALLISTIC_GRAVITY * time_delta_seconds;
+    velocity * (1.0 - BALLISTIC_DRAG * time_delta_seconds).max(0.0)
+}
+
+/// show where a ballistic projectile would go if it is thrown now
+pub(crate) fn draw_trajectory(
+    weapons: Res<Weapons>,
+    terrain: Res<Terrain>,
+    query: Query<(Entity, &Transform), With<DefaultCamera>>,
+    mut dots: Query<
+        (&mut Transform, &mut Visibility),
+        (With<TrajectoryDot>, Without<DefaultCamera>),
+    >,
+    windows: Res<Windows>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+) {
+    let weapon = &weapons.selected().weapon;
+    let mut points = vec![];
+    if weapon.kind == ProjectileKind::Ballistic {
+        if let Some((camera, camera_transform)) = query.iter().next() {
+            let mouse_ray = selector::cursor_ray(&windows, &cameras, camera);
+            let direction = launch_direction(camera_transform, mouse_ray.as_ref());
+            let mut location = launch_location(camera_transform, direction);
+            let mut velocity = direction * weapon.speed;
+            while points.len() < TRAJECTORY_DOTS {
+                velocity = ballistic_velocity(velocity, TRAJECTORY_STEP);
+                let travel = velocity * TRAJECTORY_STEP;
+                let ray = Ray::new(location.into(), travel.into());
+                if let Some(intersection) = terrain.cast_ray(&ray, 1.0) {
+                    points.push(ray.point_at(intersection.toi).into());
+                    break;
+                }
+                location += travel;
+                points.push(location);
+            }
+        }
+    }
+
+    let mut points = points.into_iter();
+    for (mut transform, mut visibility) in dots.iter_mut() {
+        match points.next() {
+            Some(point) => {
+                transform.translation = point;
+                visibility.is_visible = true;
+            }
+            None => visibility.is_visible = false,
+        }
+    }
+}
+
+/// fire the selected weapon at the mouse pointing direction,
+/// as often as the cooldown of the weapon allows regardless of the frame rate
 pub(crate) fn spawn_projectile(
     time: Res<Time>,
     mut commands: Commands,
-    mouse_buttons: Res<Input<MouseButton>>,
-    keys: Res<Input<KeyCode>>,
-    query: Query<&Transform, With<DefaultCamera>>,
+    actions: Res<ActionMap>,
+    query: Query<(Entity, &Transform), With<DefaultCamera>>,
     windows: Res<Windows>,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    camera_rig: Res<CameraRig>,
-    camera: Query<&Camera>,
+    mut weapons: ResMut<Weapons>,
+    mut pool: ResMut<ProjectilePool>,
+    mut pooled: Query<
+        (
+            &mut Transform,
+            &mut Projectile,
+            &mut Visibility,
+            &mut Handle<Mesh>,
+            &mut Handle<StandardMaterial>,
+        ),
+        Without<DefaultCamera>,
+    >,
+    broad_phase: Res<BroadPhase<MeshCollider>>,
+    cubes: Query<(&GlobalTransform, &MeshCollider), With<IkCubes>>,
+    cameras: Query<(&Camera, &GlobalTransform)>,
+    picking: Res<Picking>,
+    mut firing: Local<Option<usize>>,
 ) {
-    if keys.pressed(KeyCode::LShift) && mouse_buttons.pressed(MouseButton::Left) {
-        let mouse_ray = selector::cursor_ray(windows, camera, camera_rig);
-        for camera_transform in query.iter() {
-            let direction: Vec3 = if PROJECTILE_FROM_CENTER {
-                camera_transform.forward()
-            } else {
-                mouse_ray.dir.into()
+    // the cursor over the HUD or the minimap is clicking them instead of firing
+    if !actions.pressed(Action::Fire) || picking.is_over(PickLayer::Ui) {
+        *firing = None;
+        return;
+    }
+    // a weapon that was idle, blocked by the HUD or just switched to starts firing again
+    let resumed = *firing != Some(weapons.selected);
+    *firing = Some(weapons.selected);
+    let now = time.seconds_since_startup();
+    let slot = weapons.selected_mut();
+    let shots = slot.fire(now, resumed);
+    if shots.is_empty() {
+        return;
+    }
+
+    let kind = slot.weapon.kind;
+    let speed = slot.weapon.speed;
+    let spread = slot.weapon.spread;
+    let mesh = slot.mesh.clone();
+    let material = slot.material.clone();
+
+    for (camera, camera_transform) in query.iter() {
+        // the cursor is the same for all of the shots of this frame
+        let mouse_ray = selector::cursor_ray(&windows, &cameras, camera);
+        // the target is picked once when firing, the projectile keeps seeking it afterwards
+        let target = if PROJECTILE_SEEK_TARGET && kind == ProjectileKind::Straight {
+            selector::component_under_cursory_ray(&broad_phase, &cubes, &windows, &cameras, camera)
+                .map(|(cube, _hit)| cube)
+        } else {
+            None
+        };
+        for &fired in shots.iter() {
+            let direction = apply_spread(
+                launch_direction(camera_transform, mouse_ray.as_ref()),
+                spread,
+            );
+            let origin = launch_location(camera_transform, direction);
+            // shots fired in between frames have already travelled since then
+            let elapsed = (now - fired) as f32;
+            let transform = Transform {
+                translation: origin + direction * speed * elapsed,
+                ..Default::default()
+            };
+            let projectile = Projectile {
+                kind,
+                direction,
+                speed,
+                fired,
+                origin,
+                active: true,
             };
 
-            let offset = 10.0; //offset meters away in front of the camera
-            commands
-                .spawn_bundle(PbrBundle {
-                    mesh: meshes.add(Mesh::from(shape::Icosphere {
-                        radius: 0.1,
-                        subdivisions: 8,
-                    })),
-                    material: materials.add(StandardMaterial {
-                        base_color: Color::GREEN,
-                        metallic: 1.0,
-                        perceptual_roughness: 0.0,
-                        ..Default::default()
-                    }),
-                    transform: Transform {
-                        translation: camera_transform.translation + direction.normalize() * offset,
+            let entity = match pool.0.pop() {
+                Some(entity) => {
+                    let (
+                        mut pooled_transform,
+                        mut pooled_projectile,
+                        mut visibility,
+                        mut pooled_mesh,
+                        mut pooled_material,
+                    ) = pooled
+                        .get_mut(entity)
+                        .expect("pooled entity must be a projectile");
+                    *pooled_transform = transform;
+                    *pooled_projectile = projectile;
+                    *pooled_mesh = mesh.clone();
+                    *pooled_material = material.clone();
+                    visibility.is_visible = true;
+                    entity
+                }
+                None => commands
+                    .spawn_bundle(PbrBundle {
+                        mesh: mesh.clone(),
+                        material: material.clone(),
+                        transform,
                         ..Default::default()
-                    },
-                    ..Default::default()
-                })
-                .insert(Projectile {
-                    direction,
-                    fired: time.seconds_since_startup(),
-                });
+                    })
+                    .insert(projectile)
+                    .id(),
+            };
+
+            if let Some(target) = target {
+                commands.entity(entity).insert(Target(target));
+            } else {
+                commands.entity(entity).remove::<Target>();
+            }
         }
     }
 }
 
+/// turn the projectile towards its target, no faster than the turn rate
+/// and speed it up to the seek speed
+fn seek_target(projectile: &mut Projectile, location: Vec3, target: Vec3, time_delta_seconds: f32) {
+    projectile.speed = (projectile.speed + PROJECTILE_SEEK_ACCELERATION * time_delta_seconds)
+        .min(PROJECTILE_SEEK_SPEED);
+
+    let desired = (target - location).normalize_or_zero();
+    if desired == Vec3::ZERO {
+        return;
+    }
+    let angle = projectile.direction.angle_between(desired);
+    let max_turn = PROJECTILE_SEEK_TURN_RATE * time_delta_seconds;
+    if angle <= max_turn {
+        projectile.direction = desired;
+    } else {
+        let axis = projectile
+            .direction
+            .cross(desired)
+            .try_normalize()
+            .unwrap_or(Vec3::Y);
+        projectile.direction =
+            (Quat::from_axis_angle(axis, max_turn) * projectile.direction).normalize();
+    }
+}
+
+/// move the projectiles, sweeping a ray over the distance travelled this frame
+/// so fast projectiles don't tunnel through thin objects
 pub(crate) fn move_projectile(
-    mut commands: Commands,
     time: Res<Time>,
-    mut query: Query<(&mut Transform, Entity, &mut Projectile)>,
+    terrain: Res<Terrain>,
+    broad_phase: Res<BroadPhase<MeshCollider>>,
+    cubes: Query<(&GlobalTransform, &MeshCollider), With<IkCubes>>,
+    targets: Query<&GlobalTransform>,
+    mut pool: ResMut<ProjectilePool>,
+    mut impacts: EventWriter<ProjectileImpact>,
+    mut query: Query<(
+        &mut Transform,
+        Entity,
+        &mut Projectile,
+        &mut Visibility,
+        Option<&Target>,
+    )>,
 ) {
     let time_delta_seconds: f32 = time.delta_seconds();
     let seconds_since_startup = time.seconds_since_startup();
-    for (mut transform, entity, projectile) in query.iter_mut() {
+    for (mut transform, entity, mut projectile, mut visibility, target) in query.iter_mut() {
+        if !projectile.active {
+            continue;
+        }
         let projectile_time = seconds_since_startup - projectile.fired;
-        transform.translation +=
-            projectile.direction * time_delta_seconds * PROJECTILE_LAUNCH_SPEED;
+        if projectile_time > PROJECTILE_MAX_LIFETIME
+            || transform.translation.distance(projectile.origin) > PROJECTILE_MAX_RANGE
+        {
+            pool.retire(entity, &mut projectile, &mut visibility);
+            continue;
+        }
+
+        // a target that is gone leaves the projectile flying straight
+        if let Some(target_transform) = target.and_then(|target| targets.get(target.0).ok()) {
+            seek_target(
+                &mut projectile,
+                transform.translation,
+                target_transform.translation,
+                time_delta_seconds,
+            );
+        }
+
+        let mut velocity = projectile.direction * projectile.speed;
+        if projectile.kind == ProjectileKind::Ballistic {
+            velocity = ballistic_velocity(velocity, time_delta_seconds);
+            projectile.direction = velocity.normalize_or_zero();
+            projectile.speed = velocity.length();
+        }
+
+        let travel = velocity * time_delta_seconds;
+        // the ray spans the travelled distance, so a time of impact of 1.0 is the end of this step
+        let ray = Ray::new(transform.translation.into(), travel.into());
+
+        let terrain_hit = terrain.cast_ray(&ray, 1.0);
+        let cube_hit: Option<RayIntersection> = broad_phase
+            .cast_ray(&ray, 1.0, |cube| {
+                let (cube_transform, cube) = cubes.get(cube).ok()?;
+                selector::cast_ray(&ray, cube_transform, cube, 1.0)
+            })
+            .map(|(_, intersection)| intersection);
+
+        // ballistic projectiles bounce off the terrain, losing speed until they come to rest
+        if let (ProjectileKind::Ballistic, Some(intersection)) = (projectile.kind, terrain_hit) {
+            let cube_first = cube_hit.map_or(false, |cube_hit| cube_hit.toi < intersection.toi);
+            let normal: Vec3 = intersection.normal.into();
+            let bounced = (velocity - 2.0 * velocity.dot(normal) * normal) * BALLISTIC_RESTITUTION;
+            if !cube_first && bounced.length() >= BALLISTIC_REST_SPEED {
+                let point: Vec3 = ray.point_at(intersection.toi).into();
+                transform.translation = point + normal * 0.01;
+                projectile.direction = bounced.normalize();
+                projectile.speed = bounced.length();
+                continue;
+            }
+        }
+
+        let closest: Option<RayIntersection> = terrain_hit
+            .into_iter()
+            .chain(cube_hit)
+            .min_by(|a, b| a.toi.partial_cmp(&b.toi).unwrap_or(Ordering::Equal));
+
+        if let Some(intersection) = closest {
+            pool.retire(entity, &mut projectile, &mut visibility);
+            impacts.send(ProjectileImpact {
+                point: ray.point_at(intersection.toi).into(),
+            });
+        } else {
+            transform.translation += travel;
+        }
+    }
+}
+
+/// carve out the terrain around the projectile impacts
+pub(crate) fn explode_projectile(
+    mut impacts: EventReader<ProjectileImpact>,
+    mut terrain: ResMut<Terrain>,
+) {
+    for impact in impacts.iter() {
+        terrain.carve_sphere(impact.point, PROJECTILE_BLAST_RADIUS);
     }
 }
@@ -1,27 +1,31 @@
-use bevy::{ecs::query::WorldQuery, prelude::*};
-use dolly::prelude::CameraRig;
+use crate::broad_phase::BroadPhase;
+use bevy::{
+    ecs::query::{FilterFetch, WorldQuery},
+    prelude::*,
+};
 use nalgebra::Unit;
 use parry3d::{
-    math::{Isometry, Point, Real, Vector},
+    math::{Isometry, Real},
     query::{Ray, RayCast, RayIntersection},
-    shape::{Cuboid, HalfSpace},
+    shape::HalfSpace,
 };
-use std::{cmp::Ordering, collections::HashMap};
 
+/// the ray from the camera through the cursor, using where the camera entity is and its projection,
+/// `None` when the cursor is not over the window the camera renders to
 pub fn cursor_ray(
-    windows: Res<Windows>,
-    camera: Query<&Camera>,
-    camera_rig: Res<CameraRig>,
-) -> Ray {
-    let camera = camera.iter().next().expect("must have camera");
-    let window = match windows.get(camera.window) {
-        Some(window) => window,
-        None => {
-            panic!("WindowId {} does not exist", camera.window);
-        }
-    };
-    let cursor_pos_screen = window.cursor_position().unwrap_or(Vec2::ZERO);
-    cursor_position_to_ray(cursor_pos_screen, window, camera, camera_rig)
+    windows: &Windows,
+    cameras: &Query<(&Camera, &GlobalTransform)>,
+    camera: Entity,
+) -> Option<Ray> {
+    let (camera, camera_transform) = cameras.get(camera).ok()?;
+    let window = windows.get(camera.window)?;
+    let cursor_pos_screen = window.cursor_position()?;
+    Some(cursor_position_to_ray(
+        cursor_pos_screen,
+        window,
+        camera,
+        camera_transform,
+    ))
 }
 
 /// Convert cursor position in screen to a ray
@@ -30,88 +34,99 @@ fn cursor_position_to_ray(
     cursor_pos_screen: Vec2,
     window: &Window,
     camera: &Camera,
-    camera_rig: Res<CameraRig>,
+    camera_transform: &GlobalTransform,
 ) -> Ray {
-    let camera_transform = Transform {
-        translation: camera_rig.final_transform.position,
-        rotation: camera_rig.final_transform.rotation,
-        scale: Vec3::ONE,
-    };
     let view = camera_transform.compute_matrix();
 
-    let screen_size = Vec2::from([window.width() as f32, window.height() as f32]);
+    let screen_size = Vec2::from([window.width(), window.height()]);
     let projection = camera.projection_matrix;
 
     // 2D Normalized device coordinate cursor position from (-1, -1) to (1, 1)
     let cursor_ndc: Vec2 = (cursor_pos_screen / screen_size) * 2.0 - Vec2::from([1.0, 1.0]);
     let ndc_to_world: Mat4 = view * projection.inverse();
-    let world_to_ndc = projection * view;
     let is_orthographic = projection.w_axis[3] == 1.0;
 
     // Compute the cursor position at the near plane. The bevy camera looks at -Z.
-    let ndc_near: f32 = world_to_ndc.transform_point3(-Vec3::Z * camera.near).z;
-    let cursor_pos_near: Vec3 = ndc_to_world.transform_point3(cursor_ndc.extend(ndc_near));
-    println!("cursor_pos_near: {}", cursor_pos_near);
+    let ndc_near: f32 = projection.project_point3(-Vec3::Z * camera.near).z;
+    let cursor_pos_near: Vec3 = ndc_to_world.project_point3(cursor_ndc.extend(ndc_near));
 
-    // Compute the ray's direction depending on the projection used.
-    let ray_direction = match is_orthographic {
-        true => view.transform_vector3(-Vec3::Z), // All screenspace rays are parallel in ortho
-        false => cursor_pos_near - camera_transform.translation, // Direction from camera to cursor
+    // Compute the ray's origin and direction depending on the projection used.
+    let (ray_origin, ray_direction) = match is_orthographic {
+        // All screenspace rays are parallel in ortho, starting from the cursor on the near plane
+        true => (cursor_pos_near, view.transform_vector3(-Vec3::Z)),
+        // Direction from camera to cursor
+        false => (
+            camera_transform.translation,
+            cursor_pos_near - camera_transform.translation,
+        ),
     };
 
-    Ray::new(camera_transform.translation.into(), ray_direction.into())
+    Ray::new(ray_origin.into(), ray_direction.into())
+}
+
+/// cast the ray against the component placed by the whole transform, including its rotation and
+/// non-uniform scale, the normal of the intersection is in world space
+pub(crate) fn cast_ray<T: RayCast>(
+    ray: &Ray,
+    transform: &GlobalTransform,
+    component: &T,
+    max_toi: Real,
+) -> Option<RayIntersection> {
+    // the ray is brought into the space of the component,
+    // the time of impact along the local ray is the same as along the world ray
+    let world_to_local = transform.compute_matrix().inverse();
+    let origin: Vec3 = ray.origin.into();
+    let dir: Vec3 = ray.dir.into();
+    let local_ray = Ray::new(
+        world_to_local.transform_point3(origin).into(),
+        world_to_local.transform_vector3(dir).into(),
+    );
+    component
+        .cast_local_ray_and_get_normal(&local_ray, max_toi, true)
+        .map(|intersection| {
+            // normals go back to world space with the inverse transpose,
+            // which keeps them perpendicular to the scaled surface
+            let normal: Vec3 = intersection.normal.into();
+            let normal = Mat3::from_mat4(world_to_local).transpose() * normal;
+            RayIntersection::new(
+                intersection.toi,
+                normal.normalize_or_zero().into(),
+                intersection.feature,
+            )
+        })
 }
 
 /// an algorithmn to test which of the components is under the cursor if a ray is to be casted
-/// from the cursor location to the scene
-pub(crate) fn component_under_cursory_ray<T>(
-    components: Query<(&GlobalTransform, &T)>,
-    windows: Res<Windows>,
-    camera_rig: Res<CameraRig>,
-    camera: Query<&Camera>,
-) -> Option<(usize, Vec3)>
+/// from the cursor location to the scene, only the ones in the way of the ray are tested
+pub(crate) fn component_under_cursory_ray<T, F>(
+    broad_phase: &BroadPhase<T>,
+    components: &Query<(&GlobalTransform, &T), F>,
+    windows: &Windows,
+    cameras: &Query<(&Camera, &GlobalTransform)>,
+    camera: Entity,
+) -> Option<(Entity, Vec3)>
 where
     T: Component + RayCast,
+    F: WorldQuery,
+    F::Fetch: FilterFetch,
 {
-    let ray = cursor_ray(windows, camera, camera_rig);
+    let ray = cursor_ray(windows, cameras, camera)?;
 
-    let closest: Option<(usize, f32)> = components
-        .iter()
-        .enumerate()
-        .filter_map(|(index, (transform, component))| {
-            let isometry = Isometry::new(transform.translation.into(), Vector::identity());
-
-            component
-                .cast_ray_and_get_normal(&isometry, &ray, f32::INFINITY, true)
-                .map(|intersection| {
-                    let hit = ray.point_at(intersection.toi);
-                    (index, intersection.toi)
-                })
+    broad_phase
+        .cast_ray(&ray, f32::INFINITY, |entity| {
+            let (transform, component) = components.get(entity).ok()?;
+            cast_ray(&ray, transform, component, f32::INFINITY)
         })
-        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
-
-    if let Some((i, closest)) = closest {
-        let hit = ray.point_at(closest);
-        Some((i, hit.into()))
-    } else {
-        None
-    }
+        .map(|(entity, intersection)| (entity, ray.point_at(intersection.toi).into()))
 }
 
-pub(crate) fn intersect_half_space(
-    ray: Ray,
-    hit_impact: Vec3,
-    camera_location: Vec3,
-) -> Option<Vec3> {
-    let half_space = HalfSpace::new(Vector::z_axis());
+/// where the ray goes through the plane at the point facing towards the normal
+pub(crate) fn intersect_plane(ray: &Ray, point: Vec3, normal: Vec3) -> Option<Vec3> {
+    let half_space = HalfSpace::new(Unit::new_normalize(normal.into()));
 
-    let space_transform = Isometry::face_towards(
-        &hit_impact.into(),
-        &camera_location.into(),
-        &Vector::y_axis(),
-    );
+    let space_transform = Isometry::translation(point.x, point.y, point.z);
     half_space
-        .cast_ray_and_get_normal(&space_transform, &ray, f32::INFINITY, true)
+        .cast_ray_and_get_normal(&space_transform, ray, f32::INFINITY, true)
         .map(|intersection| {
             let hit = ray.point_at(intersection.toi);
             hit.into()
@@ -0,0 +1,680 @@
+use crate::picking::{PickHit, PickLayer, Picking};
+use crate::worldgen::WorldGenerator;
+use bevy::{
+    prelude::*,
+    render::{mesh::Indices, render_resource::PrimitiveTopology},
+};
+use dolly::prelude::CameraRig;
+use parry3d::{
+    math::{Isometry, Vector},
+    query::{self, Ray, RayIntersection, TOI},
+    shape::{Ball, Cuboid, FeatureId, Shape},
+};
+use std::collections::{HashMap, HashSet};
+
+/// number of voxels along each side of a chunk
+pub const CHUNK_SIZE: i32 = 16;
+/// size of a single voxel in meters
+pub const VOXEL_SIZE: f32 = 1.0;
+/// how many chunks around the camera are kept spawned horizontally
+const VIEW_DISTANCE: i32 = 4;
+/// the lowest chunk layer, the world has no blocks below this
+pub const MIN_CHUNK_Y: i32 = -4;
+/// the highest chunk layer, the world has no blocks above this
+pub const MAX_CHUNK_Y: i32 = 1;
+/// limit the number of chunks meshed per frame to avoid stutters when moving fast
+const MAX_CHUNKS_MESHED_PER_FRAME: usize = 4;
+/// the most voxels a ray walks through before giving up
+const MAX_RAY_STEPS: usize = 1024;
+/// how many times a swept sphere slides along the blocks it runs into in a single move
+const MAX_SWEEP_SLIDES: usize = 3;
+/// gap kept in between a swept sphere and the blocks it stops against
+const SWEEP_SKIN: f32 = 0.001;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Block {
+    Air,
+    Grass,
+    Dirt,
+    Stone,
+    DeepStone,
+    Coal,
+    Iron,
+    Gold,
+    Bedrock,
+}
+
+impl Block {
+    /// all the blocks which are rendered
+    pub const SOLID: [Block; 8] = [
+        Block::Grass,
+        Block::Dirt,
+        Block::Stone,
+        Block::DeepStone,
+        Block::Coal,
+        Block::Iron,
+        Block::Gold,
+        Block::Bedrock,
+    ];
+
+    pub fn is_solid(&self) -> bool {
+        !matches!(self, Block::Air)
+    }
+
+    /// how many seconds it takes to mine this block, `None` if it can not be mined
+    pub fn hardness(&self) -> Option<f32> {
+        match self {
+            Block::Air | Block::Bedrock => None,
+            Block::Grass => Some(0.5),
+            Block::Dirt => Some(0.75),
+            Block::Stone => Some(1.5),
+            Block::DeepStone => Some(2.5),
+            Block::Coal => Some(2.0),
+            Block::Iron => Some(3.0),
+            Block::Gold => Some(3.5),
+        }
+    }
+
+    /// whether this block is an ore worth digging out
+    pub fn is_ore(&self) -> bool {
+        matches!(self, Block::Coal | Block::Iron | Block::Gold)
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Block::Air => Color::NONE,
+            Block::Grass => Color::rgb(0.3, 0.6, 0.2),
+            Block::Dirt => Color::rgb(0.45, 0.3, 0.15),
+            Block::Stone => Color::rgb(0.5, 0.5, 0.5),
+            Block::DeepStone => Color::rgb(0.3, 0.3, 0.35),
+            Block::Coal => Color::rgb(0.1, 0.1, 0.1),
+            Block::Iron => Color::rgb(0.75, 0.55, 0.45),
+            Block::Gold => Color::rgb(1.0, 0.8, 0.1),
+            Block::Bedrock => Color::rgb(0.15, 0.12, 0.15),
+        }
+    }
+}
+
+/// voxel storage of a chunk, the blocks are stored as an index into the palette
+/// so that chunks which are mostly a single block stay small
+#[derive(Clone, Debug)]
+pub struct Chunk {
+    palette: Vec<Block>,
+    voxels: Vec<u8>,
+}
+
+impl Chunk {
+    pub fn filled(block: Block) -> Self {
+        Chunk {
+            palette: vec![block],
+            voxels: vec![0; (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize],
+        }
+    }
+
+    fn index(local: IVec3) -> usize {
+        (local.x + local.z * CHUNK_SIZE + local.y * CHUNK_SIZE * CHUNK_SIZE) as usize
+    }
+
+    /// get the block at the local voxel coordinate of this chunk
+    pub fn get(&self, local: IVec3) -> Block {
+        self.palette[self.voxels[Self::index(local)] as usize]
+    }
+
+    /// set the block at the local voxel coordinate of this chunk
+    pub fn set(&mut self, local: IVec3, block: Block) {
+        let palette_index = match self.palette.iter().position(|b| *b == block) {
+            Some(palette_index) => palette_index,
+            None => {
+                self.palette.push(block);
+                self.palette.len() - 1
+            }
+        };
+        self.voxels[Self::index(local)] = palette_index as u8;
+    }
+
+    /// whether every voxel in this chunk is air
+    pub fn is_empty(&self) -> bool {
+        self.palette
+            .iter()
+            .enumerate()
+            .all(|(i, block)| !block.is_solid() || !self.voxels.contains(&(i as u8)))
+    }
+}
+
+/// marks the entity which holds the meshes of the chunk at this chunk coordinate
+#[derive(Component)]
+pub struct TerrainChunk {
+    pub coord: IVec3,
+}
+
+/// where a shape swept through the terrain ends up
+pub struct Sweep {
+    pub center: Vec3,
+    /// the normals of the blocks the shape ran into, pointing away from the blocks
+    pub normals: Vec<Vec3>,
+}
+
+/// the voxel world, chunks that are generated are kept here even when not spawned
+/// so modifications to it are not lost when the camera moves away
+pub struct Terrain {
+    generator: WorldGenerator,
+    chunks: HashMap<IVec3, Chunk>,
+    spawned: HashMap<IVec3, Entity>,
+    dirty: HashSet<IVec3>,
+}
+
+impl Terrain {
+    pub fn new(generator: WorldGenerator) -> Self {
+        Terrain {
+            generator,
+            chunks: HashMap::new(),
+            spawned: HashMap::new(),
+            dirty: HashSet::new(),
+        }
+    }
+
+    /// the chunk coordinate which contains this voxel
+    pub fn chunk_coord(voxel: IVec3) -> IVec3 {
+        IVec3::new(
+            voxel.x.div_euclid(CHUNK_SIZE),
+            voxel.y.div_euclid(CHUNK_SIZE),
+            voxel.z.div_euclid(CHUNK_SIZE),
+        )
+    }
+
+    fn local_coord(voxel: IVec3) -> IVec3 {
+        IVec3::new(
+            voxel.x.rem_euclid(CHUNK_SIZE),
+            voxel.y.rem_euclid(CHUNK_SIZE),
+            voxel.z.rem_euclid(CHUNK_SIZE),
+        )
+    }
+
+    /// the voxel which contains this world location
+    pub fn world_to_voxel(location: Vec3) -> IVec3 {
+        (location / VOXEL_SIZE).floor().as_ivec3()
+    }
+
+    /// the world location of the minimum corner of this voxel
+    pub fn voxel_to_world(voxel: IVec3) -> Vec3 {
+        voxel.as_vec3() * VOXEL_SIZE
+    }
+
+    /// the block at this voxel, chunks that are not generated yet are treated as air
+    pub fn block_at(&self, voxel: IVec3) -> Block {
+        self.chunks
+            .get(&Self::chunk_coord(voxel))
+            .map(|chunk| chunk.get(Self::local_coord(voxel)))
+            .unwrap_or(Block::Air)
+    }
+
+    /// whether the chunk containing this voxel is generated, the blocks of the
+    /// chunks that are not are all air
+    pub fn is_generated(&self, voxel: IVec3) -> bool {
+        self.chunks.contains_key(&Self::chunk_coord(voxel))
+    }
+
+    /// the world height of the top most block generated at this column
+    pub fn surface_height(&self, x: i32, z: i32) -> i32 {
+        self.generator.surface_height(x, z)
+    }
+
+    /// replace the block at this voxel and schedule the affected chunks for remeshing
+    pub fn set_block(&mut self, voxel: IVec3, block: Block) {
+        let coord = Self::chunk_coord(voxel);
+        let local = Self::local_coord(voxel);
+        if let Some(chunk) = self.chunks.get_mut(&coord) {
+            chunk.set(local, block);
+            self.dirty.insert(coord);
+            // faces of the neighboring chunk are exposed when a border voxel changes
+            for axis in [IVec3::X, IVec3::Y, IVec3::Z] {
+                let along = local.dot(axis);
+                if along == 0 {
+                    self.dirty.insert(coord - axis);
+                }
+                if along == CHUNK_SIZE - 1 {
+                    self.dirty.insert(coord + axis);
+                }
+            }
+        }
+    }
+
+    /// remove the blocks that can be mined within the sphere, bedrock is left untouched
+    pub fn carve_sphere(&mut self, center: Vec3, radius: f32) -> Vec<(IVec3, Block)> {
+        let min = Self::world_to_voxel(center - Vec3::splat(radius));
+        let max = Self::world_to_voxel(center + Vec3::splat(radius));
+        let mut carved = vec![];
+        for y in min.y..=max.y {
+            for z in min.z..=max.z {
+                for x in min.x..=max.x {
+                    let voxel = IVec3::new(x, y, z);
+                    let voxel_center = Self::voxel_to_world(voxel) + Vec3::splat(VOXEL_SIZE / 2.0);
+                    if voxel_center.distance(center) > radius {
+                        continue;
+                    }
+                    let block = self.block_at(voxel);
+                    if block.hardness().is_some() {
+                        self.set_block(voxel, Block::Air);
+                        carved.push((voxel, block));
+                    }
+                }
+            }
+        }
+        carved
+    }
+
+    /// cast a ray against the solid blocks by walking the voxels it passes through,
+    /// the time of impact is in the unit of the ray direction like the rest of parry3d
+    pub fn cast_ray(&self, ray: &Ray, max_toi: f32) -> Option<RayIntersection> {
+        let origin: Vec3 = ray.origin.into();
+        let dir: Vec3 = ray.dir.into();
+        if dir == Vec3::ZERO {
+            return None;
+        }
+
+        let mut voxel = Self::world_to_voxel(origin);
+        if self.block_at(voxel).is_solid() {
+            return Some(RayIntersection::new(0.0, -ray.dir, FeatureId::Unknown));
+        }
+
+        let mut step = IVec3::ZERO;
+        let mut t_delta = Vec3::splat(f32::INFINITY);
+        let mut t_max = Vec3::splat(f32::INFINITY);
+        for axis in 0..3 {
+            if dir[axis] > 0.0 {
+                step[axis] = 1;
+                t_delta[axis] = VOXEL_SIZE / dir[axis];
+                t_max[axis] =
+                    (Self::voxel_to_world(voxel + IVec3::ONE)[axis] - origin[axis]) / dir[axis];
+            } else if dir[axis] < 0.0 {
+                step[axis] = -1;
+                t_delta[axis] = -VOXEL_SIZE / dir[axis];
+                t_max[axis] = (Self::voxel_to_world(voxel)[axis] - origin[axis]) / dir[axis];
+            }
+        }
+
+        for _ in 0..MAX_RAY_STEPS {
+            let axis = if t_max.x < t_max.y && t_max.x < t_max.z {
+                0
+            } else if t_max.y < t_max.z {
+                1
+            } else {
+                2
+            };
+            let toi = t_max[axis];
+            if toi > max_toi {
+                return None;
+            }
+            voxel[axis] += step[axis];
+            t_max[axis] += t_delta[axis];
+
+            if self.block_at(voxel).is_solid() {
+                let mut normal = Vec3::ZERO;
+                normal[axis] = -step[axis] as f32;
+                return Some(RayIntersection::new(toi, normal.into(), FeatureId::Unknown));
+            }
+        }
+        None
+    }
+
+    /// move a sphere by the motion, sliding along the solid blocks it runs into,
+    /// returns where the center of the sphere ends up
+    pub fn sweep_sphere(&self, center: Vec3, radius: f32, motion: Vec3) -> Vec3 {
+        self.sweep_shape(&Ball::new(radius), center, motion).center
+    }
+
+    /// move a shape by the motion, sliding along the solid blocks it runs into
+    pub fn sweep_shape(&self, shape: &dyn Shape, center: Vec3, motion: Vec3) -> Sweep {
+        let cuboid = Cuboid::new(Vector::repeat(VOXEL_SIZE / 2.0));
+        let extents: Vec3 = shape.compute_local_aabb().half_extents().into();
+        let mut sweep = Sweep {
+            center,
+            normals: vec![],
+        };
+        let mut motion = motion;
+        for _ in 0..MAX_SWEEP_SLIDES {
+            if motion == Vec3::ZERO {
+                break;
+            }
+            let center = sweep.center;
+            let min = Self::world_to_voxel(center.min(center + motion) - extents);
+            let max = Self::world_to_voxel(center.max(center + motion) + extents);
+            let mut first: Option<TOI> = None;
+            for z in min.z..=max.z {
+                for y in min.y..=max.y {
+                    for x in min.x..=max.x {
+                        let voxel = IVec3::new(x, y, z);
+                        if !self.block_at(voxel).is_solid() {
+                            continue;
+                        }
+                        let block = Self::voxel_to_world(voxel) + Vec3::splat(VOXEL_SIZE / 2.0);
+                        let hit = query::time_of_impact(
+                            &Isometry::translation(center.x, center.y, center.z),
+                            &motion.into(),
+                            shape,
+                            &Isometry::translation(block.x, block.y, block.z),
+                            &Vector::zeros(),
+                            &cuboid,
+                            1.0,
+                        );
+                        if let Ok(Some(hit)) = hit {
+                            // a block already touched does not hold back moving away from it
+                            let normal: Vec3 = hit.normal1.into_inner().into();
+                            if normal.dot(motion) <= 0.0 {
+                                continue;
+                            }
+                            if first.map_or(true, |first| hit.toi < first.toi) {
+                                first = Some(hit);
+                            }
+                        }
+                    }
+                }
+            }
+
+            match first {
+                Some(hit) => {
+                    let normal: Vec3 = -Vec3::from(hit.normal1.into_inner());
+                    sweep.center += motion * hit.toi + normal * SWEEP_SKIN;
+                    sweep.normals.push(normal);
+                    // slide along the block with what is left of the motion
+                    let remaining = motion * (1.0 - hit.toi);
+                    motion = remaining - normal * remaining.dot(normal);
+                }
+                None => {
+                    sweep.center += motion;
+                    break;
+                }
+            }
+        }
+        sweep
+    }
+
+    fn ensure_generated(&mut self, coord: IVec3) {
+        let generator = &self.generator;
+        self.chunks
+            .entry(coord)
+            .or_insert_with(|| generator.generate_chunk(coord));
+    }
+}
+
+/// the material used for each of the block types
+pub struct BlockMaterials(HashMap<Block, Handle<StandardMaterial>>);
+
+impl BlockMaterials {
+    pub fn get(&self, block: Block) -> Handle<StandardMaterial> {
+        self.0[&block].clone()
+    }
+}
+
+pub(crate) fn setup(mut commands: Commands, mut materials: ResMut<Assets<StandardMaterial>>) {
+    let block_materials = Block::SOLID
+        .into_iter()
+        .map(|block| {
+            let material = materials.add(StandardMaterial {
+                base_color: block.color(),
+                perceptual_roughness: 1.0,
+                ..Default::default()
+            });
+            (block, material)
+        })
+        .collect();
+    commands.insert_resource(BlockMaterials(block_materials));
+    commands.insert_resource(Terrain::new(WorldGenerator::from_env()));
+}
+
+/// the terrain is picked on the lowest layer, the hit is on the entity of the chunk
+/// holding the block the cursor is over
+pub(crate) fn pick_terrain(terrain: Res<Terrain>, mut picking: ResMut<Picking>) {
+    let ray = match picking.ray() {
+        Some(ray) => ray,
+        None => return,
+    };
+    if let Some(intersection) = terrain.cast_ray(&ray, f32::INFINITY) {
+        let point: Vec3 = ray.point_at(intersection.toi).into();
+        let normal: Vec3 = intersection.normal.into();
+        let voxel = Terrain::world_to_voxel(point - normal * VOXEL_SIZE / 2.0);
+        if let Some(chunk) = terrain.spawned.get(&Terrain::chunk_coord(voxel)) {
+            let hit = PickHit {
+                entity: *chunk,
+                layer: PickLayer::Terrain,
+                point,
+                normal,
+            };
+            picking.add_hit(intersection.toi, hit);
+        }
+    }
+}
+
+/// spawn the chunks around the camera and despawn the ones that are now too far away
+pub(crate) fn stream_chunks(
+    mut commands: Commands,
+    camera_rig: Res<CameraRig>,
+    mut terrain: ResMut<Terrain>,
+) {
+    let center = Terrain::chunk_coord(Terrain::world_to_voxel(camera_rig.final_transform.position));
+
+    let far_away: Vec<IVec3> = terrain
+        .spawned
+        .keys()
+        .filter(|coord| {
+            (coord.x - center.x).abs() > VIEW_DISTANCE || (coord.z - center.z).abs() > VIEW_DISTANCE
+        })
+        .copied()
+        .collect();
+    for coord in far_away {
+        if let Some(entity) = terrain.spawned.remove(&coord) {
+            commands.entity(entity).despawn_recursive();
+        }
+        terrain.dirty.remove(&coord);
+    }
+
+    for z in -VIEW_DISTANCE..=VIEW_DISTANCE {
+        for x in -VIEW_DISTANCE..=VIEW_DISTANCE {
+            for y in MIN_CHUNK_Y..=MAX_CHUNK_Y {
+                let coord = IVec3::new(center.x + x, y, center.z + z);
+                if terrain.spawned.contains_key(&coord) {
+                    continue;
+                }
+                // the neighbors are needed to know which of the border faces are hidden
+                terrain.ensure_generated(coord);
+                for axis in [IVec3::X, IVec3::Y, IVec3::Z] {
+                    terrain.ensure_generated(coord + axis);
+                    terrain.ensure_generated(coord - axis);
+                }
+                let entity = commands
+                    .spawn()
+                    .insert(TerrainChunk { coord })
+                    .insert(Transform::from_translation(Terrain::voxel_to_world(
+                        coord * CHUNK_SIZE,
+                    )))
+                    .insert(GlobalTransform::default())
+                    .id();
+                terrain.spawned.insert(coord, entity);
+                terrain.dirty.insert(coord);
+            }
+        }
+    }
+}
+
+/// rebuild the meshes of the chunks which blocks have changed
+pub(crate) fn remesh_chunks(
+    mut commands: Commands,
+    camera_rig: Res<CameraRig>,
+    mut terrain: ResMut<Terrain>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    block_materials: Res<BlockMaterials>,
+) {
+    let center = Terrain::chunk_coord(Terrain::world_to_voxel(camera_rig.final_transform.position));
+    let mut dirty: Vec<IVec3> = terrain
+        .dirty
+        .iter()
+        .filter(|coord| terrain.spawned.contains_key(coord))
+        .copied()
+        .collect();
+    // mesh the chunks nearest to the view first
+    dirty.sort_by_key(|coord| (*coord - center).abs().max_element());
+
+    for coord in dirty.into_iter().take(MAX_CHUNKS_MESHED_PER_FRAME) {
+        terrain.dirty.remove(&coord);
+        let entity = terrain.spawned[&coord];
+        commands.entity(entity).despawn_descendants();
+
+        let block_meshes = greedy_mesh(&terrain, coord);
+        commands.entity(entity).with_children(|parent| {
+            for (block, mesh) in block_meshes {
+                parent.spawn_bundle(PbrBundle {
+                    mesh: meshes.add(mesh),
+                    material: block_materials.get(block),
+                    ..Default::default()
+                });
+            }
+        });
+    }
+}
+
+#[derive(Default)]
+struct MeshBuilder {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    indices: Vec<u32>,
+}
+
+impl MeshBuilder {
+    /// add a quad at `origin` spanning `du` and `dv`, facing towards `normal`
+    fn add_quad(&mut self, origin: Vec3, du: Vec3, dv: Vec3, normal: Vec3, flip: bool) {
+        let start = self.positions.len() as u32;
+        let corners = [origin, origin + du, origin + du + dv, origin + dv];
+        let (w, h) = (du.length() / VOXEL_SIZE, dv.length() / VOXEL_SIZE);
+        let uvs = [[0.0, 0.0], [w, 0.0], [w, h], [0.0, h]];
+        for (corner, uv) in corners.iter().zip(uvs) {
+            self.positions.push((*corner).into());
+            self.normals.push(normal.into());
+            self.uvs.push(uv);
+        }
+        let quad = if flip {
+            [0, 2, 1, 0, 3, 2]
+        } else {
+            [0, 1, 2, 0, 2, 3]
+        };
+        self.indices.extend(quad.iter().map(|i| start + i));
+    }
+
+    fn build(self) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
+        mesh.set_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
+        mesh.set_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.set_indices(Some(Indices::U32(self.indices)));
+        mesh
+    }
+}
+
+/// build one mesh per block type of the chunk, merging adjacent coplanar faces
+/// of the same block into bigger quads
+fn greedy_mesh(terrain: &Terrain, coord: IVec3) -> Vec<(Block, Mesh)> {
+    let mut builders: HashMap<Block, MeshBuilder> = HashMap::new();
+    let chunk = match terrain.chunks.get(&coord) {
+        Some(chunk) if !chunk.is_empty() => chunk,
+        _ => return vec![],
+    };
+    let base = coord * CHUNK_SIZE;
+    let block_at = |local: IVec3| {
+        if local.min_element() >= 0 && local.max_element() < CHUNK_SIZE {
+            chunk.get(local)
+        } else {
+            terrain.block_at(base + local)
+        }
+    };
+
+    let size = CHUNK_SIZE as usize;
+    for d in 0..3 {
+        let u = (d + 1) % 3;
+        let v = (d + 2) % 3;
+        let mut axis = IVec3::ZERO;
+        axis[d] = 1;
+
+        // the visible face at each cell of the slice, and whether it faces the negative axis
+        let mut mask: Vec<Option<(Block, bool)>> = vec![None; size * size];
+        for slice in 0..=CHUNK_SIZE {
+            for j in 0..CHUNK_SIZE {
+                for i in 0..CHUNK_SIZE {
+                    let mut local = IVec3::ZERO;
+                    local[d] = slice;
+                    local[u] = i;
+                    local[v] = j;
+                    let behind = block_at(local - axis);
+                    let front = block_at(local);
+                    // only emit the faces of the voxels which belongs to this chunk
+                    mask[i as usize + j as usize * size] =
+                        if behind.is_solid() && !front.is_solid() && slice > 0 {
+                            Some((behind, false))
+                        } else if front.is_solid() && !behind.is_solid() && slice < CHUNK_SIZE {
+                            Some((front, true))
+                        } else {
+                            None
+                        };
+                }
+            }
+
+            for j in 0..size {
+                let mut i = 0;
+                while i < size {
+                    let face = match mask[i + j * size] {
+                        Some(face) => face,
+                        None => {
+                            i += 1;
+                            continue;
+                        }
+                    };
+                    let mut width = 1;
+                    while i + width < size && mask[i + width + j * size] == Some(face) {
+                        width += 1;
+                    }
+                    let mut height = 1;
+                    'grow: while j + height < size {
+                        for k in 0..width {
+                            if mask[i + k + (j + height) * size] != Some(face) {
+                                break 'grow;
+                            }
+                        }
+                        height += 1;
+                    }
+                    for h in 0..height {
+                        for k in 0..width {
+                            mask[i + k + (j + h) * size] = None;
+                        }
+                    }
+
+                    let (block, backface) = face;
+                    let mut origin = Vec3::ZERO;
+                    origin[d] = slice as f32;
+                    origin[u] = i as f32;
+                    origin[v] = j as f32;
+                    let mut du = Vec3::ZERO;
+                    du[u] = width as f32;
+                    let mut dv = Vec3::ZERO;
+                    dv[v] = height as f32;
+                    let normal = if backface {
+                        -axis.as_vec3()
+                    } else {
+                        axis.as_vec3()
+                    };
+                    builders.entry(block).or_default().add_quad(
+                        origin * VOXEL_SIZE,
+                        du * VOXEL_SIZE,
+                        dv * VOXEL_SIZE,
+                        normal,
+                        backface,
+                    );
+                    i += width;
+                }
+            }
+        }
+    }
+
+    builders
+        .into_iter()
+        .map(|(block, builder)| (block, builder.build()))
+        .collect()
+}
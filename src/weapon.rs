@@ -0,0 +1,233 @@
+use crate::actions::{Action, ActionMap};
+use crate::projectile::{ProjectileKind, BALLISTIC_LAUNCH_SPEED, PROJECTILE_LAUNCH_SPEED};
+use bevy::prelude::*;
+use serde::Deserialize;
+
+/// the weapon definitions are loaded from this file, the built-in weapons are used if it is missing
+const WEAPONS_FILE: &str = "assets/weapons.ron";
+/// the shortest cooldown a weapon can have, about a frame
+const MIN_WEAPON_COOLDOWN: f32 = 1.0 / 60.0;
+
+/// the look of the projectile fired by a weapon
+#[derive(Deserialize, Clone, Debug)]
+pub struct ProjectileShape {
+    pub radius: f32,
+    pub color: (f32, f32, f32),
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct Weapon {
+    pub name: String,
+    pub kind: ProjectileKind,
+    /// seconds in between two shots
+    pub cooldown: f32,
+    /// speed of the projectile at launch
+    pub speed: f32,
+    /// the most the shots deviate from where the weapon is aimed, in degrees
+    pub spread: f32,
+    /// the number of shots the weapon starts with, `None` for unlimited
+    pub ammo: Option<u32>,
+    pub projectile: ProjectileShape,
+}
+
+/// a weapon the player carries along with its remaining ammo and projectile assets
+pub struct WeaponSlot {
+    pub weapon: Weapon,
+    pub ammo: Option<u32>,
+    /// the time the weapon can fire again
+    pub next_fire: f64,
+    pub mesh: Handle<Mesh>,
+    pub material: Handle<StandardMaterial>,
+}
+
+pub struct Weapons {
+    pub slots: Vec<WeaponSlot>,
+    pub selected: usize,
+}
+
+impl WeaponSlot {
+    /// fire the weapon while its trigger is held, returns the times of the shots fired up to now,
+    /// a short cooldown can fire more than once in a frame, `resumed` when the weapon
+    /// was not firing the frame before so it fires right away instead of catching up
+    pub fn fire(&mut self, now: f64, resumed: bool) -> Vec<f64> {
+        if resumed {
+            self.next_fire = self.next_fire.max(now);
+        }
+        let mut shots = vec![];
+        while self.next_fire <= now && self.ammo != Some(0) {
+            shots.push(self.next_fire);
+            self.next_fire += self.weapon.cooldown as f64;
+            if let Some(ammo) = self.ammo.as_mut() {
+                *ammo -= 1;
+            }
+        }
+        shots
+    }
+}
+
+impl Weapons {
+    pub fn selected(&self) -> &WeaponSlot {
+        &self.slots[self.selected]
+    }
+
+    pub fn selected_mut(&mut self) -> &mut WeaponSlot {
+        &mut self.slots[self.selected]
+    }
+}
+
+fn builtin_weapons() -> Vec<Weapon> {
+    vec![
+        Weapon {
+            name: "Blaster".to_string(),
+            kind: ProjectileKind::Straight,
+            cooldown: 0.15,
+            speed: PROJECTILE_LAUNCH_SPEED,
+            spread: 0.5,
+            ammo: None,
+            projectile: ProjectileShape {
+                radius: 0.1,
+                color: (0.0, 1.0, 0.0),
+            },
+        },
+        Weapon {
+            name: "Dynamite".to_string(),
+            kind: ProjectileKind::Ballistic,
+            cooldown: 1.0,
+            speed: BALLISTIC_LAUNCH_SPEED,
+            spread: 0.0,
+            ammo: Some(20),
+            projectile: ProjectileShape {
+                radius: 0.15,
+                color: (1.0, 0.27, 0.0),
+            },
+        },
+    ]
+}
+
+fn load_weapons() -> Vec<Weapon> {
+    let mut weapons = read_weapons();
+    for weapon in weapons.iter_mut() {
+        if weapon.cooldown.is_nan() || weapon.cooldown < MIN_WEAPON_COOLDOWN {
+            warn!(
+                "{} has a cooldown of {}, using {} instead",
+                weapon.name, weapon.cooldown, MIN_WEAPON_COOLDOWN
+            );
+            weapon.cooldown = MIN_WEAPON_COOLDOWN;
+        }
+    }
+    weapons
+}
+
+fn read_weapons() -> Vec<Weapon> {
+    let weapons = std::fs::read_to_string(WEAPONS_FILE)
+        .map_err(|e| e.to_string())
+        .and_then(|content| ron::from_str::<Vec<Weapon>>(&content).map_err(|e| e.to_string()));
+    match weapons {
+        Ok(weapons) if !weapons.is_empty() => weapons,
+        Ok(_) => {
+            warn!(
+                "{} has no weapons, using the built-in weapons",
+                WEAPONS_FILE
+            );
+            builtin_weapons()
+        }
+        Err(e) => {
+            warn!(
+                "unable to load {}: {}, using the built-in weapons",
+                WEAPONS_FILE, e
+            );
+            builtin_weapons()
+        }
+    }
+}
+
+pub(crate) fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let slots = load_weapons()
+        .into_iter()
+        .map(|weapon| {
+            let (r, g, b) = weapon.projectile.color;
+            WeaponSlot {
+                ammo: weapon.ammo,
+                next_fire: 0.0,
+                mesh: meshes.add(Mesh::from(shape::Icosphere {
+                    radius: weapon.projectile.radius,
+                    subdivisions: 8,
+                })),
+                material: materials.add(StandardMaterial {
+                    base_color: Color::rgb(r, g, b),
+                    metallic: 1.0,
+                    perceptual_roughness: 0.0,
+                    ..Default::default()
+                }),
+                weapon,
+            }
+        })
+        .collect();
+    commands.insert_resource(Weapons { slots, selected: 0 });
+}
+
+/// select the weapon slot bound to the weapon slot actions
+pub(crate) fn switch_weapon(actions: Res<ActionMap>, mut weapons: ResMut<Weapons>) {
+    for slot in 0..weapons.slots.len() {
+        if actions.just_pressed(Action::WeaponSlot(slot)) {
+            weapons.selected = slot;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a weapon with a cooldown of a whole number of frames at 64 frames per second
+    fn slot(ammo: Option<u32>) -> WeaponSlot {
+        let mut weapon = builtin_weapons().remove(0);
+        weapon.cooldown = 0.125;
+        WeaponSlot {
+            weapon,
+            ammo,
+            next_fire: 0.0,
+            mesh: Handle::default(),
+            material: Handle::default(),
+        }
+    }
+
+    /// hold the trigger from the frame after `start` until `end`
+    fn hold(slot: &mut WeaponSlot, start: f64, end: f64) -> Vec<f64> {
+        let frame = 1.0 / 64.0;
+        let frames = ((end - start) / frame).round() as usize;
+        (1..=frames)
+            .flat_map(|i| slot.fire(start + i as f64 * frame, false))
+            .collect()
+    }
+
+    #[test]
+    fn idle_weapon_fires_one_shot_then_once_per_cooldown() {
+        let mut slot = slot(None);
+        assert_eq!(slot.fire(10.0, true), vec![10.0]);
+        assert_eq!(hold(&mut slot, 10.0, 10.375), vec![10.125, 10.25, 10.375]);
+
+        // let go and press again much later, there is no burst to catch up
+        assert_eq!(slot.fire(20.0, true), vec![20.0]);
+        assert_eq!(hold(&mut slot, 20.0, 20.375), vec![20.125, 20.25, 20.375]);
+    }
+
+    #[test]
+    fn idle_weapon_spends_one_ammo() {
+        let mut slot = slot(Some(2));
+        assert_eq!(slot.fire(10.0, true).len(), 1);
+        assert_eq!(slot.ammo, Some(1));
+    }
+
+    #[test]
+    fn short_cooldown_fires_more_than_once_in_a_frame() {
+        let mut slot = slot(None);
+        slot.weapon.cooldown = 1.0 / 256.0;
+        assert_eq!(slot.fire(10.0, true).len(), 1);
+        assert_eq!(slot.fire(10.0 + 1.0 / 64.0, false).len(), 4);
+    }
+}
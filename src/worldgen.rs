@@ -0,0 +1,324 @@
+use crate::terrain::{Block, Chunk, CHUNK_SIZE, MIN_CHUNK_Y};
+use bevy::prelude::*;
+
+/// the seed used when none is given through the `MINING_GAME_SEED` environment variable
+pub const DEFAULT_WORLD_SEED: u64 = 0x6d69_6e69_6e67;
+
+/// describe how an ore is scattered in the rocks
+#[derive(Clone, Debug)]
+pub struct OreSettings {
+    pub block: Block,
+    /// noise threshold in 0..1 above which the ore is placed, the higher the rarer
+    pub rarity: f32,
+    /// size in meters of the features of the noise, bigger makes longer veins
+    pub vein_scale: f32,
+    /// the ore only appears in between these world heights
+    pub min_height: i32,
+    pub max_height: i32,
+}
+
+#[derive(Clone, Debug)]
+pub struct WorldGenSettings {
+    /// the average height of the surface
+    pub surface_height: f32,
+    /// how much the hills go above and below the surface height
+    pub hill_amplitude: f32,
+    /// size in meters of the hills
+    pub hill_scale: f32,
+    /// the hills flatten out to the surface height within this many meters of the origin,
+    /// where the IK arm and the player start
+    pub spawn_flat_radius: f32,
+    /// the hills rise back up over this many meters around the flat spawn area
+    pub spawn_blend_distance: f32,
+    /// thickness of the dirt below the grass
+    pub dirt_depth: i32,
+    /// below this height stone turns into deep stone
+    pub deep_stone_height: i32,
+    /// size in meters of the cave tunnels noise
+    pub cave_scale: f32,
+    /// how wide the cave tunnels are, 0 disables the caves
+    pub cave_width: f32,
+    /// caves do not break through this many blocks below the surface
+    pub cave_roof: i32,
+    pub ores: Vec<OreSettings>,
+}
+
+impl Default for WorldGenSettings {
+    fn default() -> Self {
+        WorldGenSettings {
+            surface_height: 0.0,
+            hill_amplitude: 6.0,
+            hill_scale: 48.0,
+            spawn_flat_radius: 8.0,
+            spawn_blend_distance: 16.0,
+            dirt_depth: 3,
+            deep_stone_height: -32,
+            cave_scale: 24.0,
+            cave_width: 0.08,
+            cave_roof: 5,
+            ores: vec![
+                OreSettings {
+                    block: Block::Coal,
+                    rarity: 0.77,
+                    vein_scale: 4.0,
+                    min_height: -40,
+                    max_height: 0,
+                },
+                OreSettings {
+                    block: Block::Iron,
+                    rarity: 0.83,
+                    vein_scale: 3.0,
+                    min_height: -56,
+                    max_height: -12,
+                },
+                OreSettings {
+                    block: Block::Gold,
+                    rarity: 0.89,
+                    vein_scale: 2.0,
+                    min_height: i32::MIN,
+                    max_height: -36,
+                },
+            ],
+        }
+    }
+}
+
+/// a deterministic world generator, the same seed and settings always produce
+/// the very same chunks
+#[derive(Clone, Debug)]
+pub struct WorldGenerator {
+    pub seed: u64,
+    pub settings: WorldGenSettings,
+}
+
+impl WorldGenerator {
+    pub fn new(seed: u64) -> Self {
+        WorldGenerator {
+            seed,
+            settings: WorldGenSettings::default(),
+        }
+    }
+
+    /// use the seed from the `MINING_GAME_SEED` environment variable if set
+    pub fn from_env() -> Self {
+        let seed = std::env::var("MINING_GAME_SEED")
+            .ok()
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or(DEFAULT_WORLD_SEED);
+        Self::new(seed)
+    }
+
+    /// the world height of the top most block at this column
+    pub fn surface_height(&self, x: i32, z: i32) -> i32 {
+        let s = &self.settings;
+        let p = Vec3::new(x as f32, 0.0, z as f32) / s.hill_scale;
+        let hills = fbm(self.feature_seed(0), p, 4) * self.spawn_falloff(x, z);
+        (s.surface_height + hills * s.hill_amplitude).floor() as i32
+    }
+
+    /// how much of the hills are kept at this column, 0 in the flat spawn area
+    /// rising smoothly to 1 past the blend distance around it
+    fn spawn_falloff(&self, x: i32, z: i32) -> f32 {
+        let s = &self.settings;
+        let distance = Vec2::new(x as f32, z as f32).length();
+        let t = ((distance - s.spawn_flat_radius) / s.spawn_blend_distance.max(f32::EPSILON))
+            .clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    /// the block at this voxel, chunks are made out of this so any voxel
+    /// can be queried without generating the chunk
+    pub fn block_at(&self, voxel: IVec3, surface: i32) -> Block {
+        let s = &self.settings;
+        let bedrock = MIN_CHUNK_Y * CHUNK_SIZE;
+        if voxel.y > surface {
+            return Block::Air;
+        }
+        if voxel.y == bedrock {
+            return Block::Bedrock;
+        }
+
+        let p = voxel.as_vec3();
+        if s.cave_width > 0.0 && voxel.y < surface - s.cave_roof && voxel.y > bedrock {
+            // the tunnels are where two noise fields are both near zero
+            let a = fbm(self.feature_seed(1), p / s.cave_scale, 3);
+            let b = fbm(self.feature_seed(2), p / s.cave_scale, 3);
+            if a.abs() < s.cave_width && b.abs() < s.cave_width {
+                return Block::Air;
+            }
+        }
+
+        if voxel.y == surface {
+            return Block::Grass;
+        }
+        if voxel.y > surface - 1 - s.dirt_depth {
+            return Block::Dirt;
+        }
+
+        for (i, ore) in s.ores.iter().enumerate() {
+            if voxel.y >= ore.min_height && voxel.y <= ore.max_height {
+                let n = value_noise(self.feature_seed(16 + i as u64), p / ore.vein_scale);
+                if n * 0.5 + 0.5 > ore.rarity {
+                    return ore.block;
+                }
+            }
+        }
+
+        if voxel.y < s.deep_stone_height {
+            Block::DeepStone
+        } else {
+            Block::Stone
+        }
+    }
+
+    pub fn generate_chunk(&self, coord: IVec3) -> Chunk {
+        let mut chunk = Chunk::filled(Block::Air);
+        let base = coord * CHUNK_SIZE;
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let surface = self.surface_height(base.x + x, base.z + z);
+                if surface < base.y {
+                    continue;
+                }
+                for y in 0..CHUNK_SIZE {
+                    let local = IVec3::new(x, y, z);
+                    let block = self.block_at(base + local, surface);
+                    if block.is_solid() {
+                        chunk.set(local, block);
+                    }
+                }
+            }
+        }
+        chunk
+    }
+
+    /// derive a seed for each of the noise fields so they don't correlate
+    fn feature_seed(&self, feature: u64) -> u64 {
+        splitmix64(self.seed ^ splitmix64(feature))
+    }
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// a random value in -1..1 at the lattice point
+fn lattice(seed: u64, p: IVec3) -> f32 {
+    let h = splitmix64(seed ^ p.x as u32 as u64);
+    let h = splitmix64(h ^ p.y as u32 as u64);
+    let h = splitmix64(h ^ p.z as u32 as u64);
+    (h >> 40) as f32 / (1u64 << 23) as f32 - 1.0
+}
+
+/// smoothly interpolated value noise in -1..1
+fn value_noise(seed: u64, p: Vec3) -> f32 {
+    let cell = p.floor();
+    let f = p - cell;
+    let cell = cell.as_ivec3();
+    // smootherstep to hide the lattice
+    let w = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+
+    let corner = |x, y, z| lattice(seed, cell + IVec3::new(x, y, z));
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), w.x);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), w.x);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), w.x);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), w.x);
+    lerp(lerp(x00, x10, w.y), lerp(x01, x11, w.y), w.z)
+}
+
+/// fractal noise, adding octaves of value noise with halving amplitudes
+fn fbm(seed: u64, p: Vec3, octaves: u32) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut total = 0.0;
+    let mut frequency = 1.0;
+    for octave in 0..octaves {
+        sum += value_noise(seed.wrapping_add(octave as u64), p * frequency) * amplitude;
+        total += amplitude;
+        amplitude *= 0.5;
+        frequency *= 2.0;
+    }
+    sum / total
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// every block of the two chunks is the same
+    fn same_blocks(a: &Chunk, b: &Chunk) -> bool {
+        (0..CHUNK_SIZE).all(|y| {
+            (0..CHUNK_SIZE).all(|z| {
+                (0..CHUNK_SIZE).all(|x| {
+                    let local = IVec3::new(x, y, z);
+                    a.get(local) == b.get(local)
+                })
+            })
+        })
+    }
+
+    #[test]
+    fn same_seed_generates_the_same_chunks() {
+        let generator = WorldGenerator::new(DEFAULT_WORLD_SEED);
+        for coord in [
+            IVec3::new(0, 0, 0),
+            IVec3::new(-3, -1, 7),
+            IVec3::new(5, MIN_CHUNK_Y, -2),
+        ] {
+            let first = generator.generate_chunk(coord);
+            let second = WorldGenerator::new(DEFAULT_WORLD_SEED).generate_chunk(coord);
+            assert!(same_blocks(&first, &second), "chunk {:?} differs", coord);
+        }
+    }
+
+    #[test]
+    fn different_seed_generates_a_different_chunk() {
+        let coord = IVec3::new(0, -1, 0);
+        let chunk = WorldGenerator::new(DEFAULT_WORLD_SEED).generate_chunk(coord);
+        let other = WorldGenerator::new(DEFAULT_WORLD_SEED + 1).generate_chunk(coord);
+        assert!(!same_blocks(&chunk, &other));
+    }
+
+    #[test]
+    fn spawn_area_is_flat() {
+        // the IK arm stands on the ground at y = 0
+        for seed in [DEFAULT_WORLD_SEED, 1234] {
+            let generator = WorldGenerator::new(seed);
+            for z in -8..=8 {
+                for x in -8..=8 {
+                    if x * x + z * z <= 64 {
+                        assert_eq!(generator.surface_height(x, z), 0, "column {} {}", x, z);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn default_seed_blocks() {
+        let generator = WorldGenerator::new(DEFAULT_WORLD_SEED);
+        assert_eq!(generator.surface_height(40, 0), 2);
+        assert_eq!(generator.surface_height(-50, 70), -1);
+
+        let top = generator.generate_chunk(IVec3::new(0, 0, 0));
+        assert_eq!(top.get(IVec3::new(0, 0, 0)), Block::Grass);
+        assert_eq!(top.get(IVec3::new(0, 1, 0)), Block::Air);
+
+        let middle = generator.generate_chunk(IVec3::new(0, -3, 0));
+        assert_eq!(middle.get(IVec3::new(0, 8, 3)), Block::Coal);
+
+        let bottom = generator.generate_chunk(IVec3::new(0, MIN_CHUNK_Y, 0));
+        assert_eq!(bottom.get(IVec3::new(0, 0, 0)), Block::Bedrock);
+        assert_eq!(bottom.get(IVec3::new(0, 4, 0)), Block::DeepStone);
+        assert_eq!(bottom.get(IVec3::new(4, 4, 12)), Block::Gold);
+        assert_eq!(bottom.get(IVec3::new(0, 8, 12)), Block::Iron);
+        assert_eq!(bottom.get(IVec3::new(12, 4, 2)), Block::Air);
+    }
+}